@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use datapak::*;
+
+const DEFAULT_PAK: &str = "data.pak";
+const DEFAULT_MANIFEST: &str = "datapak.toml";
+const DEFAULT_EXTRACT_DIR: &str = "extracted";
+
+fn usage() {
+    println!("Usage: pak <COMMAND> [PAK] [OPTIONS]");
+    println!();
+    println!("Commands:");
+    println!("  list [PAK]           Print every category with entry offsets, lengths and names");
+    println!("  extract [PAK] [DIR]  Write decoded entries to DIR (default: {})", DEFAULT_EXTRACT_DIR);
+    println!("  verify [PAK]         Check entry bounds and compare entries with the manifest files");
+    println!();
+    println!("Options:");
+    println!("  --manifest FILE      Manifest used to name entries (default: {} if present)",
+             DEFAULT_MANIFEST);
+    println!("  --key-file FILE      Key for encrypted paks (or set {})", KEY_ENV_VAR);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let manifest_file = match take_option(&mut args, "--manifest") {
+        Ok(file) => file,
+        Err(e) => fail(&e)
+    };
+    let key = match take_option(&mut args, "--key-file") {
+        Ok(key_file) => find_key(key_file.as_deref()).unwrap_or_else(|e| fail(&e.to_string())),
+        Err(e) => fail(&e)
+    };
+
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        usage();
+        return;
+    }
+
+    let command = args.remove(0);
+    let pak_file = if args.is_empty() { DEFAULT_PAK.to_string() } else { args.remove(0) };
+    let manifest = match load_manifest(manifest_file) {
+        Ok(manifest) => manifest,
+        Err(e) => fail(&e)
+    };
+
+    let loader = match key {
+        Some(key) => DataPakLoader::with_key(&pak_file, key),
+        None => DataPakLoader::new(&pak_file)
+    };
+
+    let result = loader.and_then(|mut loader| {
+        let names = EntryNames::new(&loader, manifest.as_ref())?;
+
+        match command.as_str() {
+            "list" => list(&loader, &names),
+            "extract" => {
+                let dir = if args.is_empty() { DEFAULT_EXTRACT_DIR.to_string() } else { args.remove(0) };
+                extract(&mut loader, &names, Path::new(&dir))
+            },
+            "verify" => {
+                if !verify(&mut loader, &names, manifest.as_ref())? {
+                    process::exit(1);
+                }
+                Ok(())
+            },
+            _ => fail(&format!("unknown command '{}'", command))
+        }
+    });
+
+    if let Err(e) = result {
+        fail(&format!("{}: {}", pak_file, e));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("pak: {}", message);
+    process::exit(1);
+}
+
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Ok(Some(value))
+        },
+        Some(_) => Err(format!("{} needs a value", name)),
+        None => Ok(None)
+    }
+}
+
+fn load_manifest(file_name: Option<String>) -> Result<Option<Manifest>, String> {
+    match file_name {
+        Some(file_name) => Manifest::from_file(&file_name)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", file_name, e)),
+        None if Path::new(DEFAULT_MANIFEST).exists() =>
+            Ok(Manifest::from_file(DEFAULT_MANIFEST).ok()),
+        None => Ok(None)
+    }
+}
+
+//=============================================================================
+//    EntryNames
+//=============================================================================
+// v2 paks store entry names. For v1 paks they are recovered from the manifest
+// by position, and entries without a manifest line get a numbered name.
+struct EntryNames {
+    names: Vec<Vec<String>>
+}
+
+impl EntryNames {
+    fn new(loader: &DataPakLoader, manifest: Option<&Manifest>) -> PakResult<EntryNames> {
+        let mut names = Vec::new();
+
+        for (category, category_name) in CATEGORY_NAMES.iter().enumerate() {
+            let manifest_names = match manifest {
+                Some(manifest) => manifest.entry_names(category),
+                None => Vec::new()
+            };
+
+            let mut table = Vec::new();
+            for i in 0..loader.entry_count(category)? {
+                let name = match (loader.entry_name(category, i)?, manifest_names.get(i)) {
+                    (Some(name), _) => name.to_string(),
+                    (None, Some(name)) => name.clone(),
+                    (None, None) => format!("{}/{:04}.bin", category_name.to_lowercase(), i)
+                };
+                table.push(name);
+            }
+            names.push(table);
+        }
+
+        Ok(EntryNames { names })
+    }
+
+    fn get(&self, category: usize, index: usize) -> &str {
+        &self.names[category][index]
+    }
+}
+
+//=============================================================================
+//    Commands
+//=============================================================================
+fn list(loader: &DataPakLoader, names: &EntryNames) -> PakResult<()> {
+    for (category, category_name) in CATEGORY_NAMES.iter().enumerate() {
+        let count = loader.entry_count(category)?;
+        println!("----- {} ({} entries) -----", category_name, count);
+        for i in 0..count {
+            let (from, length) = loader.entry_range(category, i)?;
+            let crc = match loader.entry_checksum(category, i)? {
+                Some(crc) => format!("{:08X}", crc),
+                None => "--------".to_string()
+            };
+            let compression = match loader.entry_compression(category, i)? {
+                (Compression::Deflate, original_length) => format!("deflate ({})", original_length),
+                (Compression::None, _) => String::new()
+            };
+            let line = format!("{:>4}  {:#010X}  {:>10}  {}  {}  {}", i, from, length, crc,
+                               names.get(category, i), compression);
+            println!("{}", line.trim_end());
+        }
+    }
+
+    Ok(())
+}
+
+fn extract(loader: &mut DataPakLoader, names: &EntryNames, dir: &Path) -> PakResult<()> {
+    for category in 0..CATEGORY_COUNT {
+        for i in 0..loader.entry_count(category)? {
+            let path = dir.join(names.get(category, i));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, loader.load(category, i)?)?;
+            println!("Extracted {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn verify(loader: &mut DataPakLoader, names: &EntryNames, manifest: Option<&Manifest>)
+        -> PakResult<bool> {
+    let mut failures = 0;
+
+    for (category, category_name) in CATEGORY_NAMES.iter().enumerate() {
+        let count = loader.entry_count(category)?;
+        // Source files by entry name
+        let sources: Option<HashMap<String, PathBuf>> = manifest.map(|manifest| {
+            manifest.entry_names(category).into_iter()
+                .zip(manifest.file_paths(category))
+                .collect()
+        });
+
+        if let Some(sources) = &sources {
+            if sources.len() != count {
+                println!("{}: pak has {} entries, manifest lists {}",
+                         category_name, count, sources.len());
+                failures += 1;
+            }
+        }
+
+        for i in 0..count {
+            let name = names.get(category, i);
+            let (from, length) = loader.entry_range(category, i)?;
+            if from + length > loader.file_size() {
+                println!("BAD   {} runs past the end of the file", name);
+                failures += 1;
+                continue;
+            }
+
+            let data = match loader.load(category, i) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("BAD   {}: {}", name, e);
+                    failures += 1;
+                    continue;
+                }
+            };
+
+            let source = sources.as_ref().and_then(|sources| sources.get(name));
+            match source.map(fs::read) {
+                Some(Ok(source_data)) if source_data != data => {
+                    println!("DIFF  {} differs from {}", name, source.unwrap().display());
+                    failures += 1;
+                },
+                Some(Err(e)) => {
+                    println!("MISS  {} cannot read {}: {}", name, source.unwrap().display(), e);
+                    failures += 1;
+                },
+                _ => println!("OK    {}", name)
+            }
+        }
+    }
+
+    if failures == 0 {
+        println!("All entries verified.");
+    } else {
+        println!("{} problem(s) found.", failures);
+    }
+
+    Ok(failures == 0)
+}
@@ -0,0 +1,65 @@
+use std::env;
+use std::io;
+use std::process;
+use datapak::*;
+
+const DEFAULT_MANIFEST: &str = "datapak.toml";
+const DEFAULT_OUTPUT: &str = "data.pak";
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut version = PakVersion::V2;
+    if let Some(i) = args.iter().position(|arg| arg == "--v1") {
+        args.remove(i);
+        version = PakVersion::V1;
+    }
+
+    let mut key_file = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--key-file") {
+        if i + 1 >= args.len() {
+            eprintln!("pakbuild: --key-file needs a value");
+            process::exit(1);
+        }
+        key_file = Some(args.remove(i + 1));
+        args.remove(i);
+    }
+
+    if args.len() > 2 || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("Usage: pakbuild [--v1] [--key-file FILE] [MANIFEST] [OUTPUT]");
+        println!("  MANIFEST         defaults to {}", DEFAULT_MANIFEST);
+        println!("  OUTPUT           defaults to {}", DEFAULT_OUTPUT);
+        println!("  --v1             write the legacy layout without entry names");
+        println!("  --key-file FILE  encrypt entries with the key in FILE ({} hex digits)",
+                 KEY_BYTE_COUNT * 2);
+        println!("The key can also be given in the {} environment variable.", KEY_ENV_VAR);
+        return;
+    }
+
+    let manifest_file = args.first().map(String::as_str).unwrap_or(DEFAULT_MANIFEST);
+    let output_file = args.get(1).map(String::as_str).unwrap_or(DEFAULT_OUTPUT);
+
+    if let Err(e) = build(manifest_file, output_file, version, key_file.as_deref()) {
+        eprintln!("pakbuild: {}", e);
+        process::exit(1);
+    }
+}
+
+fn build(manifest_file: &str, output_file: &str, version: PakVersion, key_file: Option<&str>)
+        -> io::Result<()> {
+    println!("Reading {}", manifest_file);
+    let manifest = Manifest::from_file(manifest_file)?;
+    let mut builder = DataPakBuilder::from_manifest(&manifest, version)?;
+
+    if let Some(key) = find_key(key_file)? {
+        if version == PakVersion::V1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "v1 paks cannot be encrypted"));
+        }
+        println!("Encrypting entries");
+        builder.set_key(key);
+    }
+
+    builder.save(output_file)?;
+    println!("Written {}", output_file);
+    Ok(())
+}
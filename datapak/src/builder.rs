@@ -0,0 +1,276 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Write, BufWriter};
+use crate::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PakVersion {
+    V1, V2
+}
+
+struct DataEntry {
+    name: String,
+    data: Vec<u8>
+}
+
+// An entry as it is written to the file: compressed (if worthwhile) and encoded
+struct StoredEntry<'a> {
+    entry: &'a DataEntry,
+    compression: Compression,
+    data: Vec<u8>
+}
+
+//=============================================================================
+//    DataPakBuilder
+//=============================================================================
+pub struct DataPakBuilder {
+    version: PakVersion,
+    compression: [Compression; CATEGORY_COUNT],
+    key: Option<PakKey>,
+    data_tables: Vec<Vec<DataEntry>>
+}
+
+impl DataPakBuilder {
+    pub fn new(version: PakVersion) -> DataPakBuilder {
+        let mut data_tables = Vec::new();
+        data_tables.resize_with(CATEGORY_COUNT, Vec::new);
+        DataPakBuilder {
+            version,
+            compression: [Compression::None; CATEGORY_COUNT],
+            key: None,
+            data_tables
+        }
+    }
+
+    pub fn from_manifest(manifest: &Manifest, version: PakVersion) -> io::Result<DataPakBuilder> {
+        let mut builder = DataPakBuilder::new(version);
+
+        for category in 0..CATEGORY_COUNT {
+            builder.set_compression(category, manifest.sections()[category].compression);
+
+            let names = manifest.entry_names(category);
+            for (name, path) in names.into_iter().zip(manifest.file_paths(category)) {
+                println!("Adding {}", path.display());
+                let data = fs::read(&path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+                })?;
+                builder.add(category, &name, data);
+            }
+        }
+
+        Ok(builder)
+    }
+
+    // Names are only written to v2 paks
+    pub fn add(&mut self, category: usize, name: &str, data: Vec<u8>) {
+        self.data_tables[category].push(DataEntry { name: name.to_string(), data });
+    }
+
+    // Compression is only applied to v2 paks
+    pub fn set_compression(&mut self, category: usize, compression: Compression) {
+        self.compression[category] = compression;
+    }
+
+    // Encrypting with a key is only supported by v2 paks
+    pub fn set_key(&mut self, key: PakKey) {
+        self.key = Some(key);
+    }
+
+    pub fn save(&self, file_name: &str) -> io::Result<()> {
+        let f = File::create(file_name)?;
+        let mut writer = BufWriter::new(f);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let flags = self.flags();
+
+        // The v2 file checksum covers everything after the header, so the body
+        // is assembled in memory first
+        let mut body: Vec<u8> = Vec::new();
+        let mut body_offset = match self.version {
+            PakVersion::V1 => SIGNATURE_BYTE_COUNT,
+            PakVersion::V2 => PAK_V2_SIGNATURE.len() as u32 + 2 * U32_BYTE_COUNT
+        };
+
+        let nonce = cipher::new_nonce();
+        if let (Some(key), PakVersion::V2) = (&self.key, self.version) {
+            body.write_all(&nonce)?;
+            body.write_all(&cipher::key_check(key, &nonce))?;
+            body_offset += (cipher::NONCE_BYTE_COUNT + cipher::KEY_CHECK_BYTE_COUNT) as u32;
+        }
+        self.write_body(&mut body, body_offset, flags, &nonce)?;
+
+        // Write file signature and header flags
+        match self.version {
+            PakVersion::V1 => {
+                writer.write_all(PAK_FILE_SIGNATURE.as_bytes())?;
+            },
+            PakVersion::V2 => {
+                writer.write_all(PAK_V2_SIGNATURE.as_bytes())?;
+                writer.write_all(&flags.to_le_bytes())?;
+                writer.write_all(&crc32fast::hash(&body).to_le_bytes())?;
+            }
+        }
+
+        writer.write_all(&body)
+    }
+
+//=============================================================================
+//  Private methods (DataPakBuilder)
+//=============================================================================
+    fn flags(&self) -> u32 {
+        match self.version {
+            PakVersion::V1 => 0,
+            PakVersion::V2 => {
+                let mut flags = FLAG_CHECKSUMS;
+                if self.compression.iter().any(|c| *c != Compression::None) {
+                    flags |= FLAG_COMPRESSION;
+                }
+                if self.key.is_some() {
+                    flags |= FLAG_CIPHER;
+                }
+                flags
+            }
+        }
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W, body_offset: u32, flags: u32,
+                            nonce: &cipher::PakNonce) -> io::Result<()> {
+        let stored_tables: Vec<Vec<StoredEntry>> = self.data_tables.iter().enumerate()
+            .map(|(category, table)| {
+                table.iter().enumerate()
+                    .map(|(index, entry)| self.store(category, index, entry, nonce))
+                    .collect()
+            })
+            .collect();
+
+        let mut offset = body_offset + CATEGORY_COUNT as u32 * U32_BYTE_COUNT;
+
+        // Write offsets to categories
+        for table in &stored_tables {
+            writer.write_all(&offset.to_le_bytes())?;
+            offset += U32_BYTE_COUNT;
+            for stored in table {
+                offset += entry_record_size(stored.entry, flags, self.version)
+                    + stored.data.len() as u32;
+            }
+        }
+
+        // Write asset tables, each followed by its encoded data
+        for table in &stored_tables {
+            writer.write_all(&(table.len() as u32).to_le_bytes())?;
+            for stored in table {
+                let entry = stored.entry;
+                if self.version == PakVersion::V2 {
+                    writer.write_all(&(entry.name.len() as u16).to_le_bytes())?;
+                    writer.write_all(entry.name.as_bytes())?;
+                }
+                writer.write_all(&(stored.data.len() as u32).to_le_bytes())?;
+                if flags & FLAG_CHECKSUMS != 0 {
+                    writer.write_all(&crc32fast::hash(&entry.data).to_le_bytes())?;
+                }
+                if flags & FLAG_COMPRESSION != 0 {
+                    writer.write_all(&[stored.compression.id()])?;
+                    writer.write_all(&(entry.data.len() as u32).to_le_bytes())?;
+                }
+            }
+
+            for stored in table {
+                writer.write_all(&stored.data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Entries that do not shrink (e.g. PNG images) are stored uncompressed
+    fn store<'a>(&self, category: usize, index: usize, entry: &'a DataEntry,
+                 nonce: &cipher::PakNonce) -> StoredEntry<'a> {
+        let mut compression = Compression::None;
+        let mut data = entry.data.clone();
+
+        if self.version == PakVersion::V2 && self.compression[category] == Compression::Deflate {
+            let compressed = miniz_oxide::deflate::compress_to_vec(&entry.data, 9);
+            if compressed.len() < entry.data.len() {
+                compression = Compression::Deflate;
+                data = compressed;
+            }
+        }
+
+        match (&self.key, self.version) {
+            (Some(key), PakVersion::V2) => {
+                cipher::apply_keystream(key, nonce, cipher::entry_slot(category, index), &mut data);
+            },
+            _ => invert_bytes(&mut data)
+        }
+        StoredEntry { entry, compression, data }
+    }
+}
+
+fn entry_record_size(entry: &DataEntry, flags: u32, version: PakVersion) -> u32 {
+    let mut size = U32_BYTE_COUNT;
+    if version == PakVersion::V2 {
+        size += U16_BYTE_COUNT + entry.name.len() as u32;
+    }
+    if flags & FLAG_CHECKSUMS != 0 {
+        size += U32_BYTE_COUNT;
+    }
+    if flags & FLAG_COMPRESSION != 0 {
+        size += 1 + U32_BYTE_COUNT;
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deflate_round_trip() {
+        let sound: Vec<u8> = (0..4096).map(|i| (i % 16) as u8).collect();
+        let mut builder = DataPakBuilder::new(PakVersion::V2);
+        builder.set_compression(CATEGORY_SND, Compression::Deflate);
+        builder.add(CATEGORY_SND, "sounds/move.wav", sound.clone());
+        builder.add(CATEGORY_SND, "sounds/short.wav", b"ab".to_vec());
+        builder.add(CATEGORY_IMG, "images/apple.png", sound.clone());
+        let path = save_test_pak(&builder, "deflate");
+
+        let mut loader = DataPakLoader::new(&path).unwrap();
+        let (compression, original_length) = loader.entry_compression(CATEGORY_SND, 0).unwrap();
+        assert_eq!(compression, Compression::Deflate);
+        assert_eq!(original_length, sound.len() as u64);
+        assert!(loader.entry_range(CATEGORY_SND, 0).unwrap().1 < sound.len() as u64);
+        assert_eq!(loader.load_by_name("sounds/move.wav").unwrap(), &sound[..]);
+
+        // Entries that would grow and categories without compression are stored as they are
+        assert_eq!(loader.entry_compression(CATEGORY_SND, 1).unwrap(), (Compression::None, 2));
+        assert_eq!(loader.load_by_name("sounds/short.wav").unwrap(), b"ab");
+        assert_eq!(loader.entry_compression(CATEGORY_IMG, 0).unwrap().0, Compression::None);
+        assert_eq!(loader.load_by_name("images/apple.png").unwrap(), &sound[..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Pak and loose assets drift apart when an asset changes without a rebuild
+    #[test]
+    fn shipped_pak_is_built_from_the_manifest() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let manifest = Manifest::from_file(&root.join("datapak.toml").to_string_lossy()).unwrap();
+        let mut built = Vec::new();
+        DataPakBuilder::from_manifest(&manifest, PakVersion::V2).unwrap().write(&mut built).unwrap();
+        let shipped = fs::read(root.join("data.pak")).unwrap();
+        assert!(shipped == built, "data.pak is out of date, rebuild it with pakbuild");
+    }
+
+    #[test]
+    fn v1_paks_are_not_compressed() {
+        let mut builder = DataPakBuilder::new(PakVersion::V1);
+        builder.set_compression(CATEGORY_SND, Compression::Deflate);
+        builder.add(CATEGORY_SND, "sounds/move.wav", vec![0; 1024]);
+        let mut file = Vec::new();
+        builder.write(&mut file).unwrap();
+        assert!(file.len() > 1024);
+    }
+}
@@ -0,0 +1,155 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chacha20::ChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+
+//=============================================================================
+//    Keyed stream cipher (ChaCha20)
+//=============================================================================
+pub const KEY_BYTE_COUNT: usize = 32;
+pub type PakKey = [u8; KEY_BYTE_COUNT];
+
+// Environment variable holding the key for the pak tools and the game build
+pub const KEY_ENV_VAR: &str = "DATAPAK_KEY";
+
+pub(crate) const NONCE_BYTE_COUNT: usize = 8;
+pub(crate) const KEY_CHECK_BYTE_COUNT: usize = 4;
+pub(crate) type PakNonce = [u8; NONCE_BYTE_COUNT];
+
+// Slot reserved for the key check value, never used by an entry
+const KEY_CHECK_SLOT: u32 = u32::MAX;
+
+// Every entry gets its own keystream: the 12-byte ChaCha20 nonce is the
+// per-file nonce followed by the entry's category and index
+pub(crate) fn entry_slot(category: usize, index: usize) -> u32 {
+    ((category as u32) << 24) | index as u32
+}
+
+pub(crate) fn apply_keystream(key: &PakKey, nonce: &PakNonce, slot: u32, buf: &mut [u8]) {
+    let mut iv = [0u8; 12];
+    iv[..NONCE_BYTE_COUNT].copy_from_slice(nonce);
+    iv[NONCE_BYTE_COUNT..].copy_from_slice(&slot.to_le_bytes());
+    let mut cipher = ChaCha20::new(key.into(), &iv.into());
+    cipher.apply_keystream(buf);
+}
+
+// Lets the loader tell a wrong key apart from damaged data
+pub(crate) fn key_check(key: &PakKey, nonce: &PakNonce) -> [u8; KEY_CHECK_BYTE_COUNT] {
+    let mut check = [0u8; KEY_CHECK_BYTE_COUNT];
+    apply_keystream(key, nonce, KEY_CHECK_SLOT, &mut check);
+    check
+}
+
+// The nonce only has to differ between builds, it does not have to be secret
+pub(crate) fn new_nonce() -> PakNonce {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(0);
+    let seed = nanos ^ ((process::id() as u64) << 32);
+    seed.to_le_bytes()
+}
+
+// Keys are written as 64 hex digits
+pub fn parse_key(text: &str) -> Option<PakKey> {
+    let text = text.trim();
+    if text.len() != KEY_BYTE_COUNT * 2 || !text.is_ascii() {
+        return None;
+    }
+
+    let mut key = [0u8; KEY_BYTE_COUNT];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+// Key from a file if one is given, otherwise from the environment
+pub fn find_key(key_file: Option<&str>) -> io::Result<Option<PakKey>> {
+    let text = match key_file {
+        Some(key_file) => fs::read_to_string(key_file)?,
+        None => match env::var(KEY_ENV_VAR) {
+            Ok(text) => text,
+            Err(_) => return Ok(None)
+        }
+    };
+
+    match parse_key(&text) {
+        Some(key) => Ok(Some(key)),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("a key must be {} hex digits", KEY_BYTE_COUNT * 2)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    const KEY_TEXT: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn encrypted_pak(name: &str) -> String {
+        let mut builder = DataPakBuilder::new(PakVersion::V2);
+        builder.set_key(parse_key(KEY_TEXT).unwrap());
+        builder.set_compression(CATEGORY_FNT, Compression::Deflate);
+        builder.add(CATEGORY_IMG, "images/apple.png", b"pixels".to_vec());
+        builder.add(CATEGORY_FNT, "fonts/big.ttf", vec![7; 2048]);
+        save_test_pak(&builder, name)
+    }
+
+    #[test]
+    fn parses_hex_keys() {
+        let key = parse_key(KEY_TEXT).unwrap();
+        assert_eq!(key[0], 0x00);
+        assert_eq!(key[31], 0x1F);
+        assert_eq!(parse_key(&format!(" {}\n", KEY_TEXT.to_uppercase())), Some(key));
+        assert_eq!(parse_key(&KEY_TEXT[2..]), None);
+        assert_eq!(parse_key(&KEY_TEXT.replace('0', "g")), None);
+    }
+
+    #[test]
+    fn keystream_is_its_own_inverse() {
+        let key = parse_key(KEY_TEXT).unwrap();
+        let nonce = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut data = b"the quick brown snake".to_vec();
+        apply_keystream(&key, &nonce, entry_slot(CATEGORY_IMG, 0), &mut data);
+        assert_ne!(&data[..], b"the quick brown snake");
+
+        // Every entry has a keystream of its own
+        let mut other = b"the quick brown snake".to_vec();
+        apply_keystream(&key, &nonce, entry_slot(CATEGORY_IMG, 1), &mut other);
+        assert_ne!(data, other);
+
+        apply_keystream(&key, &nonce, entry_slot(CATEGORY_IMG, 0), &mut data);
+        assert_eq!(&data[..], b"the quick brown snake");
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let path = encrypted_pak("encrypted");
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(6).any(|window| window == b"pixels"));
+
+        let mut loader = DataPakLoader::with_key(&path, parse_key(KEY_TEXT).unwrap()).unwrap();
+        assert!(loader.is_encrypted());
+        assert_eq!(loader.load_by_name("images/apple.png").unwrap(), b"pixels");
+        assert_eq!(loader.load_by_name("fonts/big.ttf").unwrap(), &[7; 2048][..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wrong_or_missing_key_is_reported() {
+        let path = encrypted_pak("wrong_key");
+        let mut wrong_key = parse_key(KEY_TEXT).unwrap();
+        wrong_key[0] ^= 0x80;
+        assert!(matches!(DataPakLoader::with_key(&path, wrong_key), Err(PakError::WrongKey)));
+
+        // Without a key the names can be listed, but not the data
+        let mut loader = DataPakLoader::new(&path).unwrap();
+        assert!(loader.find("images/apple.png").is_ok());
+        assert!(matches!(loader.load_by_name("images/apple.png"), Err(PakError::KeyRequired)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
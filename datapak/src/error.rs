@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use crate::CATEGORY_NAMES;
+
+//=============================================================================
+//    PakError
+//=============================================================================
+#[derive(Debug)]
+pub enum PakError {
+    Io(io::Error),
+    BadSignature,
+    TruncatedHeader,
+    CategoryOutOfRange(usize),
+    IndexOutOfRange { category: usize, index: usize },
+    NameNotFound(String),
+    BadEntryName { category: usize, index: usize },
+    ShortRead { category: usize, index: usize, expected: u64, actual: u64 },
+    EntryChecksum { category: usize, index: usize, name: Option<String> },
+    BadCompressedData { category: usize, index: usize },
+    BadEntryData { name: String, reason: String },
+    KeyRequired,
+    BadKey,
+    WrongKey,
+    FileChecksum
+}
+
+pub type PakResult<T> = Result<T, PakError>;
+
+impl fmt::Display for PakError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PakError::Io(e) => write!(f, "I/O error: {}", e),
+            PakError::BadSignature => write!(f, "not a pak file (bad signature)"),
+            PakError::TruncatedHeader => write!(f, "pak header is truncated"),
+            PakError::CategoryOutOfRange(category) =>
+                write!(f, "category #{} does not exist", category),
+            PakError::IndexOutOfRange { category, index } =>
+                write!(f, "{} entry #{} does not exist", category_name(*category), index),
+            PakError::NameNotFound(name) => write!(f, "no entry named '{}'", name),
+            PakError::BadEntryName { category, index } =>
+                write!(f, "{} entry #{} has an invalid name", category_name(*category), index),
+            PakError::ShortRead { category, index, expected, actual } =>
+                write!(f, "{} entry #{} is truncated ({} of {} bytes)",
+                       category_name(*category), index, actual, expected),
+            PakError::EntryChecksum { category, index, name: Some(name) } =>
+                write!(f, "{} ({} entry #{}) is damaged (checksum mismatch)",
+                       name, category_name(*category), index),
+            PakError::EntryChecksum { category, index, name: None } =>
+                write!(f, "{} entry #{} is damaged (checksum mismatch)",
+                       category_name(*category), index),
+            PakError::BadCompressedData { category, index } =>
+                write!(f, "{} entry #{} cannot be decompressed", category_name(*category), index),
+            PakError::BadEntryData { name, reason } => write!(f, "{} cannot be decoded ({})", name, reason),
+            PakError::KeyRequired => write!(f, "pak is encrypted and no key was given"),
+            PakError::BadKey => write!(f, "key is not 64 hex digits"),
+            PakError::WrongKey => write!(f, "pak is encrypted with a different key"),
+            PakError::FileChecksum =>
+                write!(f, "pak table of content is damaged (checksum mismatch)")
+        }
+    }
+}
+
+impl Error for PakError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PakError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for PakError {
+    fn from(e: io::Error) -> Self {
+        PakError::Io(e)
+    }
+}
+
+fn category_name(category: usize) -> &'static str {
+    CATEGORY_NAMES.get(category).copied().unwrap_or("???")
+}
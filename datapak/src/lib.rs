@@ -0,0 +1,96 @@
+mod loader;
+mod builder;
+mod manifest;
+mod error;
+mod cipher;
+mod vfs;
+
+use serde::Deserialize;
+
+pub use loader::DataPakLoader;
+pub use builder::{DataPakBuilder, PakVersion};
+pub use manifest::{Manifest, ManifestSection};
+pub use error::{PakError, PakResult};
+pub use vfs::VirtualFs;
+pub use cipher::{PakKey, KEY_BYTE_COUNT, KEY_ENV_VAR, parse_key, find_key};
+
+//=============================================================================
+//    Pak file layout
+//=============================================================================
+// v1: "PAK", category offsets, then per category a count, entry lengths and data.
+// v2: "PAK2", header flags, category offsets, then per category a count,
+//     a (name, length) record per entry and data.
+//     With FLAG_CHECKSUMS the flags are followed by a CRC32 of the rest of the
+//     file and each entry record ends with a CRC32 of the entry's original bytes.
+//     With FLAG_COMPRESSION each entry record also carries a compression method
+//     byte and the original length; the stored data is compressed before encoding.
+//     With FLAG_CIPHER the header continues with a nonce and a key check value,
+//     and entries are encoded with a keyed ChaCha20 stream instead of inversion.
+pub(crate) const PAK_FILE_SIGNATURE: &str = "PAK";
+pub(crate) const PAK_V2_SIGNATURE: &str = "PAK2";
+pub(crate) const SIGNATURE_BYTE_COUNT: u32 = 3;
+pub(crate) const U16_BYTE_COUNT: u32 = 2;
+pub(crate) const U32_BYTE_COUNT: u32 = 4;
+
+pub(crate) const FLAG_CHECKSUMS: u32 = 0x01;
+pub(crate) const FLAG_COMPRESSION: u32 = 0x02;
+pub(crate) const FLAG_CIPHER: u32 = 0x04;
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate
+}
+
+impl Compression {
+    pub(crate) fn from_id(id: u8) -> Option<Compression> {
+        match id {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Deflate),
+            _ => None
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1
+        }
+    }
+}
+
+pub const CATEGORY_IMG: usize = 0;
+pub const CATEGORY_FNT: usize = 1;
+pub const CATEGORY_SND: usize = 2;
+pub const CATEGORY_MAP: usize = 3;
+pub const CATEGORY_CFG: usize = 4;
+pub const CATEGORY_SCR: usize = 5;
+pub const CATEGORY_COUNT: usize = 6;
+
+pub const CATEGORY_NAMES: [&str; CATEGORY_COUNT] = ["IMG", "FNT", "SND", "MAP", "CFG", "SCR"];
+
+// Entry names are "<category dir>/<file name>", e.g. "images/apple.png"
+pub const CATEGORY_DIRS: [&str; CATEGORY_COUNT] =
+    ["images", "fonts", "sounds", "maps", "configs", "scripts"];
+
+pub fn entry_name(category: usize, file_name: &str) -> String {
+    format!("{}/{}", CATEGORY_DIRS[category], file_name)
+}
+
+// Byte inversion is its own inverse, so the same function encodes and decodes
+pub(crate) fn invert_bytes(buf: &mut [u8]) {
+    for byte in buf {
+        *byte = 0xFF - *byte;
+    }
+}
+
+// Writes a built pak to a file of its own in the temp dir, for the loader to open
+#[cfg(test)]
+pub(crate) fn save_test_pak(builder: &DataPakBuilder, name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("datapak_test_{}_{}.pak", std::process::id(), name));
+    let path = path.to_string_lossy().to_string();
+    builder.save(&path).unwrap();
+    path
+}
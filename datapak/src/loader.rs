@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, BufReader};
+use crate::*;
+
+struct DataChunk {
+    name: Option<String>,
+    from: u64,
+    length: u64,
+    crc: Option<u32>,
+    compression: Compression,
+    original_length: u64
+}
+
+type DataTable = Vec<DataChunk>;
+
+pub struct DataPakLoader {
+    reader: BufReader<File>,
+    version: u32,
+    flags: u32,
+    file_crc: Option<(u64, u32)>,
+    key: Option<PakKey>,
+    nonce: Option<cipher::PakNonce>,
+    data_tables: Vec<DataTable>,
+    name_index: HashMap<String, (usize, usize)>,
+    file_size: u64,
+    buf: Vec<u8>
+}
+
+impl DataPakLoader {
+    pub fn new(file_name: &str) -> PakResult<DataPakLoader> {
+        DataPakLoader::open(file_name, None)
+    }
+
+    // The key is only needed for paks built with one. Without it the table of
+    // content can still be read, but loading an entry fails.
+    pub fn with_key(file_name: &str, key: PakKey) -> PakResult<DataPakLoader> {
+        DataPakLoader::open(file_name, Some(key))
+    }
+
+    fn open(file_name: &str, key: Option<PakKey>) -> PakResult<DataPakLoader> {
+        println!("Loading {}", file_name);
+        let f = File::open(file_name)?;
+        let file_size = f.metadata()?.len();
+
+        let mut bundle_reader = DataPakLoader {
+            reader: BufReader::new(f),
+            version: 1,
+            flags: 0,
+            file_crc: None,
+            key,
+            nonce: None,
+            data_tables: Vec::new(),
+            name_index: HashMap::new(),
+            file_size,
+            buf: Vec::new()
+            };
+
+        bundle_reader.read_table_of_content()?;
+        bundle_reader.verify_file_checksum()?;
+        Ok(bundle_reader)
+    }
+
+    pub fn load_image(&mut self, index: usize) -> PakResult<&[u8]> {
+        self.load_data(CATEGORY_IMG, index)
+    }
+
+    pub fn load_font(&mut self, index: usize) -> PakResult<&[u8]> {
+        self.load_data(CATEGORY_FNT, index)
+    }
+
+    pub fn load_sound(&mut self, index: usize) -> PakResult<&[u8]> {
+        self.load_data(CATEGORY_SND, index)
+    }
+
+    pub fn load_by_name(&mut self, name: &str) -> PakResult<&[u8]> {
+        let (category, index) = self.find(name)?;
+        self.load_data(category, index)
+    }
+
+    // Returns (category, index) of a named entry
+    pub fn find(&self, name: &str) -> PakResult<(usize, usize)> {
+        self.name_index.get(name).copied().ok_or_else(|| PakError::NameNotFound(name.to_string()))
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // Only v2 paks store entry names
+    pub fn has_names(&self) -> bool {
+        self.version >= 2
+    }
+
+    // Names of every named entry, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.name_index.keys().map(String::as_str)
+    }
+
+    // Gives names to the entries of a v1 pak, by position. Entries that
+    // already have a name keep it.
+    pub fn assign_names(&mut self, category: usize, names: &[String]) -> PakResult<()> {
+        let table = self.data_tables.get_mut(category)
+            .ok_or(PakError::CategoryOutOfRange(category))?;
+
+        for (index, (chunk, name)) in table.iter_mut().zip(names).enumerate() {
+            if chunk.name.is_none() {
+                chunk.name = Some(name.clone());
+                self.name_index.insert(name.clone(), (category, index));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn entry_name(&self, category: usize, index: usize) -> PakResult<Option<&str>> {
+        Ok(self.chunk(category, index)?.name.as_deref())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_CIPHER != 0
+    }
+
+    pub fn has_checksums(&self) -> bool {
+        self.flags & FLAG_CHECKSUMS != 0
+    }
+
+    pub fn entry_checksum(&self, category: usize, index: usize) -> PakResult<Option<u32>> {
+        Ok(self.chunk(category, index)?.crc)
+    }
+
+    // Returns the compression method and the length of the entry once decompressed
+    pub fn entry_compression(&self, category: usize, index: usize)
+            -> PakResult<(Compression, u64)> {
+        let chunk = self.chunk(category, index)?;
+        Ok((chunk.compression, chunk.original_length))
+    }
+
+    pub fn entry_count(&self, category: usize) -> PakResult<usize> {
+        Ok(self.table(category)?.len())
+    }
+
+    // Returns (offset, length) of an entry inside the pak file
+    pub fn entry_range(&self, category: usize, index: usize) -> PakResult<(u64, u64)> {
+        let chunk = self.chunk(category, index)?;
+        Ok((chunk.from, chunk.length))
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn load(&mut self, category: usize, index: usize) -> PakResult<&[u8]> {
+        self.load_data(category, index)
+    }
+
+//=============================================================================
+//  Private methods (DataPakLoader)
+//=============================================================================
+    fn table(&self, category: usize) -> PakResult<&DataTable> {
+        self.data_tables.get(category).ok_or(PakError::CategoryOutOfRange(category))
+    }
+
+    fn chunk(&self, category: usize, index: usize) -> PakResult<&DataChunk> {
+        self.table(category)?.get(index).ok_or(PakError::IndexOutOfRange { category, index })
+    }
+
+    fn load_data(&mut self, category: usize, index: usize) -> PakResult<&[u8]> {
+        let (from, length) = self.entry_range(category, index)?;
+        let mut reader = self.reader.get_ref();
+        reader.seek(SeekFrom::Start(from))?;
+        let mut chunk = reader.take(length);
+        self.buf.clear();
+        let actual = chunk.read_to_end(&mut self.buf)? as u64;
+        if actual != length {
+            return Err(PakError::ShortRead { category, index, expected: length, actual });
+        }
+        self.decode(category, index)?;
+
+        let (compression, original_length) = self.entry_compression(category, index)?;
+        if compression == Compression::Deflate {
+            // Never inflates past the length in the table, whatever the data says
+            let bad_data = PakError::BadCompressedData { category, index };
+            let limit = original_length as usize;
+            let data = miniz_oxide::inflate::decompress_to_vec_with_limit(&self.buf, limit)
+                .map_err(|_| bad_data)?;
+            if data.len() as u64 != original_length {
+                return Err(PakError::BadCompressedData { category, index });
+            }
+            self.buf = data;
+        }
+
+        let chunk = self.chunk(category, index)?;
+        if let Some(crc) = chunk.crc {
+            if crc32fast::hash(&self.buf) != crc {
+                let name = chunk.name.clone();
+                return Err(PakError::EntryChecksum { category, index, name });
+            }
+        }
+
+        Ok(&self.buf[..])
+    }
+
+    // A whole-file mismatch is narrowed down to the first damaged entry, so the
+    // error can name it. If every entry is intact, the table itself is damaged.
+    fn verify_file_checksum(&mut self) -> PakResult<()> {
+        let (from, expected) = match self.file_crc {
+            Some(file_crc) => file_crc,
+            None => return Ok(())
+        };
+
+        let mut reader = self.reader.get_ref();
+        reader.seek(SeekFrom::Start(from))?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            let count = reader.read(&mut buf)?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buf[..count]);
+        }
+
+        if hasher.finalize() == expected {
+            return Ok(());
+        }
+
+        for category in 0..CATEGORY_COUNT {
+            for index in 0..self.entry_count(category)? {
+                self.load_data(category, index)?;
+            }
+        }
+
+        Err(PakError::FileChecksum)
+    }
+
+    fn decode(&mut self, category: usize, index: usize) -> PakResult<()> {
+        match (&self.nonce, &self.key) {
+            (Some(nonce), Some(key)) => {
+                let slot = cipher::entry_slot(category, index);
+                cipher::apply_keystream(key, nonce, slot, &mut self.buf);
+            },
+            (Some(_), None) => return Err(PakError::KeyRequired),
+            (None, _) => invert_bytes(&mut self.buf)
+        }
+        Ok(())
+    }
+
+    fn read_table_of_content(&mut self) -> PakResult<()> {
+        let mut reader = self.reader.get_ref();
+        let mut signature_buf = [0u8; SIGNATURE_BYTE_COUNT as usize];
+
+        // Read file signature. A v1 header continues with the first category
+        // offset, which never starts with the v2 version byte.
+        read_header(&mut reader, &mut signature_buf)?;
+        if signature_buf != PAK_FILE_SIGNATURE.as_bytes() {
+            return Err(PakError::BadSignature);
+        }
+
+        let mut version_buf = [0u8; 1];
+        read_header(&mut reader, &mut version_buf)?;
+        if version_buf[..] == PAK_V2_SIGNATURE.as_bytes()[SIGNATURE_BYTE_COUNT as usize..] {
+            self.version = 2;
+            let mut buf = [0u8; U32_BYTE_COUNT as usize];
+            read_header(&mut reader, &mut buf)?;
+            self.flags = u32::from_le_bytes(buf);
+
+            if self.has_checksums() {
+                read_header(&mut reader, &mut buf)?;
+                let from = reader.stream_position()?;
+                self.file_crc = Some((from, u32::from_le_bytes(buf)));
+            }
+
+            if self.is_encrypted() {
+                let mut nonce = [0u8; cipher::NONCE_BYTE_COUNT];
+                let mut check = [0u8; cipher::KEY_CHECK_BYTE_COUNT];
+                read_header(&mut reader, &mut nonce)?;
+                read_header(&mut reader, &mut check)?;
+                if let Some(key) = &self.key {
+                    if cipher::key_check(key, &nonce) != check {
+                        return Err(PakError::WrongKey);
+                    }
+                }
+                self.nonce = Some(nonce);
+            }
+        } else {
+            reader.seek(SeekFrom::Start(SIGNATURE_BYTE_COUNT as u64))?;
+        }
+
+        let mut offsets = [0u32; CATEGORY_COUNT];
+
+        // Read offsets to categories
+        for offset in &mut offsets {
+            let mut buf = [0u8; U32_BYTE_COUNT as usize];
+            read_header(&mut reader, &mut buf)?;
+            *offset = u32::from_le_bytes(buf);
+        }
+
+        // Build asset tables
+        for (category, offset) in offsets.iter().enumerate() {
+            let mut buf = [0u8; U32_BYTE_COUNT as usize];
+            reader.seek(SeekFrom::Start(*offset as u64))?;
+            // Read asset count
+            read_header(&mut reader, &mut buf)?;
+            let asset_count = u32::from_le_bytes(buf);
+
+            // Read asset records: name (v2 only), length, checksum and compression
+            let mut asset_table: DataTable = Vec::new();
+
+            for j in 0..asset_count as usize {
+                let name = if self.version >= 2 {
+                    Some(read_name(&mut reader, category, j)?)
+                } else {
+                    None
+                };
+                let mut buf = [0u8; U32_BYTE_COUNT as usize];
+                read_header(&mut reader, &mut buf)?;
+                let length = u32::from_le_bytes(buf) as u64;
+                let crc = if self.has_checksums() {
+                    read_header(&mut reader, &mut buf)?;
+                    Some(u32::from_le_bytes(buf))
+                } else {
+                    None
+                };
+                let (compression, original_length) = if self.flags & FLAG_COMPRESSION != 0 {
+                    let mut id_buf = [0u8; 1];
+                    read_header(&mut reader, &mut id_buf)?;
+                    let compression = Compression::from_id(id_buf[0])
+                        .ok_or(PakError::BadCompressedData { category, index: j })?;
+                    read_header(&mut reader, &mut buf)?;
+                    (compression, u32::from_le_bytes(buf) as u64)
+                } else {
+                    (Compression::None, length)
+                };
+
+                if let Some(name) = &name {
+                    self.name_index.insert(name.clone(), (category, j));
+                }
+                asset_table.push(DataChunk {
+                    name, from: 0, length, crc, compression, original_length
+                });
+            }
+
+            // Data follows the records back to back
+            let mut offset = reader.stream_position()?;
+            for chunk in &mut asset_table {
+                chunk.from = offset;
+                offset += chunk.length;
+            }
+
+            self.data_tables.push(asset_table);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_name<R: Read>(reader: &mut R, category: usize, index: usize) -> PakResult<String> {
+    let mut buf = [0u8; U16_BYTE_COUNT as usize];
+    read_header(reader, &mut buf)?;
+    let mut name_buf = vec![0u8; u16::from_le_bytes(buf) as usize];
+    read_header(reader, &mut name_buf)?;
+    String::from_utf8(name_buf).map_err(|_| PakError::BadEntryName { category, index })
+}
+
+// Running out of bytes while reading the table of content means the header is cut short
+fn read_header<R: Read>(reader: &mut R, buf: &mut [u8]) -> PakResult<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => PakError::TruncatedHeader,
+        _ => PakError::Io(e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_builder(version: PakVersion) -> DataPakBuilder {
+        let mut builder = DataPakBuilder::new(version);
+        builder.add(CATEGORY_IMG, "images/apple.png", b"apple".to_vec());
+        builder.add(CATEGORY_IMG, "images/wall.png", vec![0, 1, 2, 0xFF]);
+        builder.add(CATEGORY_SND, "sounds/eat.wav", b"crunch".to_vec());
+        builder.add(CATEGORY_MAP, "maps/empty.map", Vec::new());
+        builder
+    }
+
+    #[test]
+    fn v1_round_trip() {
+        let path = save_test_pak(&test_builder(PakVersion::V1), "v1");
+        let mut loader = DataPakLoader::new(&path).unwrap();
+        assert_eq!(loader.version(), 1);
+        assert!(!loader.has_names());
+        assert_eq!(loader.entry_count(CATEGORY_IMG).unwrap(), 2);
+        assert_eq!(loader.entry_count(CATEGORY_FNT).unwrap(), 0);
+        assert_eq!(loader.load_image(0).unwrap(), b"apple");
+        assert_eq!(loader.load_image(1).unwrap(), &[0, 1, 2, 0xFF]);
+        assert_eq!(loader.load_sound(0).unwrap(), b"crunch");
+        assert_eq!(loader.load(CATEGORY_MAP, 0).unwrap(), b"");
+        assert!(matches!(loader.load_image(2), Err(PakError::IndexOutOfRange { .. })));
+        assert!(matches!(loader.load_by_name("images/apple.png"), Err(PakError::NameNotFound(_))));
+
+        // Names come from the manifest for v1 paks
+        let names = vec!["images/apple.png".to_string(), "images/wall.png".to_string()];
+        loader.assign_names(CATEGORY_IMG, &names).unwrap();
+        assert_eq!(loader.load_by_name("images/wall.png").unwrap(), &[0, 1, 2, 0xFF]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn v2_round_trip() {
+        let path = save_test_pak(&test_builder(PakVersion::V2), "v2");
+        let mut loader = DataPakLoader::new(&path).unwrap();
+        assert_eq!(loader.version(), 2);
+        assert!(loader.has_names());
+        assert!(loader.has_checksums());
+        assert!(!loader.is_encrypted());
+        assert_eq!(loader.names().count(), 4);
+        assert_eq!(loader.find("sounds/eat.wav").unwrap(), (CATEGORY_SND, 0));
+        assert_eq!(loader.entry_name(CATEGORY_IMG, 1).unwrap(), Some("images/wall.png"));
+        assert_eq!(loader.load_by_name("images/apple.png").unwrap(), b"apple");
+        assert_eq!(loader.load_by_name("sounds/eat.wav").unwrap(), b"crunch");
+        assert_eq!(loader.load_image(1).unwrap(), &[0, 1, 2, 0xFF]);
+        assert!(matches!(loader.load_by_name("images/pear.png"), Err(PakError::NameNotFound(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Saves the pak with one byte changed
+    fn damage(path: &str, find: &[u8]) {
+        let mut bytes = std::fs::read(path).unwrap();
+        let at = bytes.windows(find.len()).position(|window| window == find).unwrap();
+        bytes[at] ^= 0x01;
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn damaged_entry_is_named() {
+        let path = save_test_pak(&test_builder(PakVersion::V2), "damaged_entry");
+        let mut stored = b"crunch".to_vec();
+        invert_bytes(&mut stored);
+        damage(&path, &stored);
+
+        match DataPakLoader::new(&path) {
+            Err(PakError::EntryChecksum { category, index, name }) => {
+                assert_eq!((category, index), (CATEGORY_SND, 0));
+                assert_eq!(name.as_deref(), Some("sounds/eat.wav"));
+            },
+            _ => panic!("damaged entry not found")
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn damaged_table_of_content_is_found() {
+        let path = save_test_pak(&test_builder(PakVersion::V2), "damaged_table");
+        damage(&path, b"images/apple.png");
+        assert!(matches!(DataPakLoader::new(&path), Err(PakError::FileChecksum)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("datapak_test_{}_bad.pak", std::process::id()));
+        std::fs::write(&path, b"ZIP\0\0\0\0").unwrap();
+        let result = DataPakLoader::new(&path.to_string_lossy());
+        assert!(matches!(result, Err(PakError::BadSignature)));
+
+        std::fs::write(&path, b"PAK2\x01").unwrap();
+        let result = DataPakLoader::new(&path.to_string_lossy());
+        assert!(matches!(result, Err(PakError::TruncatedHeader)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::{CATEGORY_COUNT, Compression, entry_name};
+
+//=============================================================================
+//    Manifest (datapak.toml)
+//=============================================================================
+#[derive(Deserialize)]
+pub struct ManifestSection {
+    pub path: String,
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub compression: Compression
+}
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub images: ManifestSection,
+    pub fonts: ManifestSection,
+    pub sounds: ManifestSection,
+    pub maps: ManifestSection,
+    pub configs: ManifestSection,
+    pub scripts: ManifestSection,
+    #[serde(skip)]
+    base_dir: PathBuf
+}
+
+impl Manifest {
+    pub fn from_file(file_name: &str) -> io::Result<Manifest> {
+        let text = fs::read_to_string(file_name)?;
+        let mut manifest: Manifest = toml::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // Asset paths are relative to the directory holding the manifest
+        manifest.base_dir = match Path::new(file_name).parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::new()
+        };
+
+        Ok(manifest)
+    }
+
+    // Sections in the same order as the pak categories
+    pub fn sections(&self) -> [&ManifestSection; CATEGORY_COUNT] {
+        [&self.images, &self.fonts, &self.sounds, &self.maps, &self.configs, &self.scripts]
+    }
+
+    pub fn entry_names(&self, category: usize) -> Vec<String> {
+        let section = self.sections()[category];
+        section.files.iter().map(|file| entry_name(category, file)).collect()
+    }
+
+    pub fn file_paths(&self, category: usize) -> Vec<PathBuf> {
+        let section = self.sections()[category];
+        let dir = self.base_dir.join(&section.path);
+        section.files.iter().map(|file| dir.join(file)).collect()
+    }
+}
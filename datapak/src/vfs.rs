@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::*;
+
+enum Mount {
+    Pak(Box<DataPakLoader>),
+    Dir(PathBuf)
+}
+
+//=============================================================================
+//    VirtualFs
+//=============================================================================
+// Paks and loose directories mounted in priority order. A name is read from
+// the last mount that has it, so a mod pak mounted after the base pak can
+// replace "images/wall.png" or add new "maps/..." entries.
+pub struct VirtualFs {
+    mounts: Vec<Mount>
+}
+
+impl VirtualFs {
+    pub fn new() -> VirtualFs {
+        VirtualFs { mounts: Vec::new() }
+    }
+
+    pub fn mount_pak(&mut self, file_name: &str, key: Option<PakKey>) -> PakResult<()> {
+        let loader = match key {
+            Some(key) => DataPakLoader::with_key(file_name, key)?,
+            None => DataPakLoader::new(file_name)?
+        };
+        self.mount_loader(loader);
+        Ok(())
+    }
+
+    pub fn mount_loader(&mut self, loader: DataPakLoader) {
+        self.mounts.push(Mount::Pak(Box::new(loader)));
+    }
+
+    // Files are looked up by entry name below the directory, e.g. <dir>/images/apple.png
+    pub fn mount_dir(&mut self, dir: &str) {
+        println!("Mounting {}", dir);
+        self.mounts.push(Mount::Dir(PathBuf::from(dir)));
+    }
+
+    pub fn mount_count(&self) -> usize {
+        self.mounts.len()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.mounts.iter().any(|mount| mount_contains(mount, name))
+    }
+
+    pub fn read(&mut self, name: &str) -> PakResult<Vec<u8>> {
+        for mount in self.mounts.iter_mut().rev() {
+            if !mount_contains(mount, name) {
+                continue;
+            }
+
+            return match mount {
+                Mount::Pak(loader) => Ok(loader.load_by_name(name)?.to_vec()),
+                Mount::Dir(dir) => Ok(fs::read(dir.join(name))?)
+            };
+        }
+
+        Err(PakError::NameNotFound(name.to_string()))
+    }
+
+    // Names of every entry in a category directory (e.g. "maps") across all mounts
+    pub fn list(&self, category_dir: &str) -> Vec<String> {
+        let prefix = format!("{}/", category_dir);
+        let mut names = BTreeSet::new();
+
+        for mount in &self.mounts {
+            match mount {
+                Mount::Pak(loader) => {
+                    names.extend(loader.names().filter(|name| name.starts_with(&prefix))
+                                               .map(String::from));
+                },
+                Mount::Dir(dir) => {
+                    // A missing directory simply has nothing to add
+                    if let Ok(files) = list_dir(&dir.join(category_dir)) {
+                        names.extend(files.into_iter().map(|file| format!("{}{}", prefix, file)));
+                    }
+                }
+            }
+        }
+
+        names.into_iter().collect()
+    }
+}
+
+impl Default for VirtualFs {
+    fn default() -> Self {
+        VirtualFs::new()
+    }
+}
+
+fn mount_contains(mount: &Mount, name: &str) -> bool {
+    match mount {
+        Mount::Pak(loader) => loader.find(name).is_ok(),
+        Mount::Dir(dir) => dir.join(name).is_file()
+    }
+}
+
+fn list_dir(dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pak(name: &str, entries: &[(usize, &str, &[u8])]) -> String {
+        let mut builder = DataPakBuilder::new(PakVersion::V2);
+        for (category, entry_name, data) in entries {
+            builder.add(*category, entry_name, data.to_vec());
+        }
+        save_test_pak(&builder, name)
+    }
+
+    fn test_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datapak_test_{}_{}", std::process::id(), name));
+        for (file, data) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn later_mounts_override_earlier_ones() {
+        let base = test_pak("vfs_base", &[
+            (CATEGORY_IMG, "images/apple.png", b"base apple"),
+            (CATEGORY_IMG, "images/wall.png", b"base wall"),
+            (CATEGORY_SND, "sounds/eat.wav", b"base eat")
+        ]);
+        let patch = test_pak("vfs_patch", &[(CATEGORY_IMG, "images/wall.png", b"patch wall")]);
+        let dir = test_dir("vfs_override", &[
+            ("images/apple.png", b"dir apple"),
+            ("sounds/eat.wav", b"dir eat")
+        ]);
+        let late = test_pak("vfs_late", &[(CATEGORY_SND, "sounds/eat.wav", b"late eat")]);
+
+        let mut vfs = VirtualFs::new();
+        vfs.mount_pak(&base, None).unwrap();
+        vfs.mount_pak(&patch, None).unwrap();
+        vfs.mount_dir(&dir.to_string_lossy());
+        vfs.mount_pak(&late, None).unwrap();
+        assert_eq!(vfs.mount_count(), 4);
+
+        assert_eq!(vfs.read("images/apple.png").unwrap(), b"dir apple");
+        assert_eq!(vfs.read("images/wall.png").unwrap(), b"patch wall");
+        assert_eq!(vfs.read("sounds/eat.wav").unwrap(), b"late eat");
+        assert!(vfs.contains("images/wall.png"));
+        assert!(!vfs.contains("images/pear.png"));
+        assert!(matches!(vfs.read("images/pear.png"), Err(PakError::NameNotFound(_))));
+
+        for pak in [base, patch, late].iter() {
+            fs::remove_file(pak).unwrap();
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn list_merges_every_mount() {
+        let base = test_pak("vfs_list_base", &[
+            (CATEGORY_MAP, "maps/cross.map", b"base cross"),
+            (CATEGORY_MAP, "maps/pillars.map", b"base pillars"),
+            (CATEGORY_IMG, "images/apple.png", b"apple")
+        ]);
+        let mod_pak = test_pak("vfs_list_mod", &[(CATEGORY_MAP, "maps/spiral.map", b"mod spiral")]);
+        let dir = test_dir("vfs_list", &[
+            ("maps/cross.map", b"dir cross"),
+            ("maps/zigzag.map", b"dir zigzag"),
+            ("images/wall.png", b"wall")
+        ]);
+
+        let mut vfs = VirtualFs::new();
+        vfs.mount_pak(&base, None).unwrap();
+        vfs.mount_pak(&mod_pak, None).unwrap();
+        vfs.mount_dir(&dir.to_string_lossy());
+        vfs.mount_dir("no/such/dir");
+
+        assert_eq!(vfs.list("maps"), vec!["maps/cross.map", "maps/pillars.map", 
+                                          "maps/spiral.map", "maps/zigzag.map"]);
+        assert_eq!(vfs.list("images"), vec!["images/apple.png", "images/wall.png"]);
+        assert!(vfs.list("fonts").is_empty());
+        assert_eq!(vfs.read("maps/cross.map").unwrap(), b"dir cross");
+
+        fs::remove_file(base).unwrap();
+        fs::remove_file(mod_pak).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_pak_is_not_mounted() {
+        let mut vfs = VirtualFs::new();
+        assert!(vfs.mount_pak("no/such/file.pak", None).is_err());
+        assert_eq!(vfs.mount_count(), 0);
+    }
+}
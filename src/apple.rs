@@ -1,60 +1,23 @@
 use macroquad::prelude::*;
-use crate::snake::*;
 use crate::gamescene::*;
+use crate::common::*;
+use crate::sim::{Apple, TICK_TIME};
 
 //=================================================================================================
-//    Apple
+//    Apple drawing
 //=================================================================================================
-pub struct Apple {
-    pub pos: Vec2,
-    pub alpha: f32
+// New apples fade in over their first seconds
+fn apple_alpha(apple: &Apple, tick_fraction: f32) -> f32 {
+    let age = (apple.age as f32 + tick_fraction) * TICK_TIME;
+    (0.25 + age * 0.6).min(1.0)
 }
 
-impl Apple {
-    pub fn random_spawn(play_area: &Rect, snakes: &[Snake]) -> Apple {
-        let mut pos: Vec2;
-        let mut retry_count: u32 = 0;
-
-        loop {
-            // Randomize position inside playing field excluding borders
-            let x: f32 = (rand::rand() % play_area.w as u32) as f32;
-            let y: f32 = (rand::rand() % play_area.h as u32) as f32;
-            // +1 to start after left & top border
-            pos = Vec2::new(x + play_area.x, y + play_area.y); 
-
-            // Do not spawn on top of the snake
-            let mut overlap_snakes: bool = false;
-            for snake in snakes {
-                if snake.has_position(&pos) {
-                    overlap_snakes = true;
-                    break;
-                }
-            }
-            if overlap_snakes && retry_count < 20 {
-                retry_count += 1;
-                continue;
-            }
-
-            break;
-        }
-
-        Apple { pos: pos, alpha: 0.25 }
-    }
-
-    pub fn update(&mut self) {
-        self.alpha += 0.01;
-        if self.alpha > 1.0 {
-            self.alpha = 1.0;
-        }
-    }
-
-    pub fn draw_basic(&mut self, scene: &GameScene) {
-        let color = Color::new(1.0, 0.0, 0.0, self.alpha);
-        scene.draw_circle(&self.pos, &color);
-    }
+pub fn draw_apple_basic(apple: &Apple, tick_fraction: f32, scene: &GameScene) {
+    let color = Color::new(1.0, 0.0, 0.0, apple_alpha(apple, tick_fraction));
+    scene.draw_circle(&pos_to_vec2(apple.pos), &color);
+}
 
-    pub fn draw(&mut self, texture: &Texture2D, scene: &GameScene) {
-        let color = Color::new(1.0, 1.0, 1.0, self.alpha);
-        scene.draw_texture(texture, &self.pos, &color);
-    }
+pub fn draw_apple(apple: &Apple, tick_fraction: f32, texture: &Texture2D, scene: &GameScene) {
+    let color = Color::new(1.0, 1.0, 1.0, apple_alpha(apple, tick_fraction));
+    scene.draw_texture(texture, &pos_to_vec2(apple.pos), &color);
 }
@@ -0,0 +1,61 @@
+//=============================================================================
+//    Arena
+//=============================================================================
+// Board size in cells and the size of a cell on screen. The window fits the
+// board with the status panel to its right.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Arena {
+    pub width: i32,
+    pub height: i32,
+    pub grid_size: f32
+}
+
+impl Arena {
+    pub const STATUS_PANEL_WIDTH: f32 = 224.0;
+    const MIN_WINDOW_HEIGHT: f32 = 480.0;
+    // Room for the four gates and for the snakes to get in
+    const MIN_WIDTH: i32 = 12;
+    const MIN_HEIGHT: i32 = 10;
+    const MAX_SIZE: i32 = 100;
+    const MIN_GRID_SIZE: f32 = 8.0;
+    const MAX_GRID_SIZE: f32 = 64.0;
+
+    pub fn medium() -> Arena {
+        Arena { width: 23, height: 20, grid_size: 32.0 }
+    }
+
+    // "small", "medium", "large" or "<width>x<height>", keeping the grid size
+    pub fn with_size_name(&self, name: &str) -> Option<Arena> {
+        let (width, height) = match name {
+            "small" => (17, 15),
+            "medium" => (23, 20),
+            "large" => (31, 24),
+            _ => {
+                let (width, height) = name.split_once('x')?;
+                (width.trim().parse().ok()?, height.trim().parse().ok()?)
+            }
+        };
+        Some(self.with_size(width, height))
+    }
+
+    pub fn with_size(&self, width: i32, height: i32) -> Arena {
+        Arena {
+            width: width.clamp(Arena::MIN_WIDTH, Arena::MAX_SIZE),
+            height: height.clamp(Arena::MIN_HEIGHT, Arena::MAX_SIZE),
+            grid_size: self.grid_size
+        }
+    }
+
+    pub fn with_grid_size(&self, grid_size: f32) -> Arena {
+        Arena { grid_size: grid_size.clamp(Arena::MIN_GRID_SIZE, Arena::MAX_GRID_SIZE), ..*self }
+    }
+
+    pub fn window_width(&self) -> f32 {
+        self.width as f32 * self.grid_size + Arena::STATUS_PANEL_WIDTH
+    }
+
+    // Tall enough for the status panel even on a small board
+    pub fn window_height(&self) -> f32 {
+        (self.height as f32 * self.grid_size).max(Arena::MIN_WINDOW_HEIGHT)
+    }
+}
@@ -1,12 +1,45 @@
 use macroquad::prelude::*;
 use macroquad::audio::*;
-use crate::datapakloader::DataPakLoader;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use datapak::*;
+use crate::common::*;
+use crate::sim::Board;
+use crate::campaign::*;
 
+//=============================================================================
+//    AssetSource
+//=============================================================================
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AssetSource {
+    Pak,    // data.pak
+    Loose   // files under assets/, no pak rebuild needed while editing art
+}
+
+impl AssetSource {
+    pub fn from_name(name: &str) -> Option<AssetSource> {
+        match name {
+            "pak" => Some(AssetSource::Pak),
+            "loose" => Some(AssetSource::Loose),
+            _ => None
+        }
+    }
+}
 
+//=============================================================================
+//    Assets
+//=============================================================================
 pub struct Assets {
     textures: Vec<Texture2D>,
     sounds: Vec<Sound>,
-    fonts: Vec<Font>
+    fonts: Vec<Font>,
+    maps: Vec<Board>,
+    campaign: Vec<CampaignLevel>,
+    watcher: Option<AssetWatcher>
 }
     
 impl Assets {
@@ -26,98 +59,216 @@ impl Assets {
     pub const TTF_RETRO: usize = 1;
     pub const FONT_COUNT: usize = 2;
 
+    // File names in the same order as the ids above
+    const TEXTURE_FILES: [&'static str; Assets::TEXTURE_COUNT] = 
+        ["apple.png", "snake1.png", "snake2.png", "wall.png"];
+    const SOUND_FILES: [&'static str; Assets::SOUND_COUNT] = 
+        ["dead.wav", "eat.wav", "get_ready.wav", "move.wav"];
+    const FONT_FILES: [&'static str; Assets::FONT_COUNT] = 
+        ["dpcomic.ttf", "gomarice_no_continue.ttf"];
+
+    const BASE_PAK: &'static str = "data.pak";
+    const ASSETS_DIR: &'static str = "assets";
+    const PATCH_PAK: &'static str = "patch.pak";
+    const MODS_DIR: &'static str = "mods";
+    const MAP_EXTENSION: &'static str = ".map";
+    const CAMPAIGN_FILE: &'static str = "campaign.toml";
+
     pub fn new() -> Assets {
         Assets { 
             textures: Vec::new(), 
             sounds: Vec::new(),
-            fonts: Vec::new() 
+            fonts: Vec::new(),
+            maps: Vec::new(),
+            campaign: Vec::new(),
+            watcher: None
         }
     }
 
-    pub async fn load(&mut self) {
-        self.add_texture("assets/images/apple.png").await;
-        self.add_texture("assets/images/snake1.png").await;
-        self.add_texture("assets/images/snake2.png").await;
-        self.add_texture("assets/images/wall.png").await;
-        
-        self.add_sound("assets/sounds/move.wav").await;
-        self.add_sound("assets/sounds/get_ready.wav").await;
-        self.add_sound("assets/sounds/dead.wav").await;
-        self.add_sound("assets/sounds/eat.wav").await;
-
-        self.add_font("assets/fonts/dpcomic.ttf").await;
-        self.add_font("assets/fonts/gomarice_no_continue.ttf").await;
-        println!("Asset loaded.");
-    }
-
-    pub async fn load_from_datapak(&mut self) {
-        let mut reader = DataPakLoader::new("data.pak");
+    pub async fn load(&mut self, source: AssetSource) -> PakResult<()> {
+        let mut vfs = Assets::mount(source)?;
 
-        for i in 0..Assets::TEXTURE_COUNT { 
+        // Both sources are read through the same names, so the ids above always
+        // match no matter where the files come from
+        for (i, file) in Assets::TEXTURE_FILES.iter().enumerate() { 
             println!("Loading image #{}", i + 1);
-            let texture = Texture2D::from_file_with_format(reader.load_image(i), 
-                Some(ImageFormat::Png));
-            texture.set_filter(FilterMode::Nearest);
-            self.textures.push(texture);
+            let name = entry_name(CATEGORY_IMG, file);
+            self.add_texture(&name, &vfs.read(&name)?)?;
         }
 
-        for i in 0..Assets::SOUND_COUNT { 
+        for (i, file) in Assets::SOUND_FILES.iter().enumerate() { 
             println!("Loading sound #{}", i + 1);
-            let result = load_sound_from_bytes(reader.load_sound(i)).await;
-            match result {
-                Ok(sound) => self.sounds.push(sound),
-                Err(_e) => panic!("Error loading sound!")
-            }
+            let name = entry_name(CATEGORY_SND, file);
+            self.add_sound(&name, &vfs.read(&name)?).await?;
         }
 
-        for i in 0..Assets::FONT_COUNT { 
+        for (i, file) in Assets::FONT_FILES.iter().enumerate() { 
             println!("Loading font #{}", i + 1);
-            let font = match load_ttf_font_from_bytes(reader.load_font(i)) {
-                Ok(font) => font,
-                Err(_e) => panic!("Error loading font!")
+            let name = entry_name(CATEGORY_FNT, file);
+            self.add_font(&name, &vfs.read(&name)?)?;
+        }
+
+        // Every map any mount has, a broken one is only left out
+        for name in vfs.list(CATEGORY_DIRS[CATEGORY_MAP]) {
+            let file_name = match name.rsplit('/').next() {
+                Some(file_name) if file_name.ends_with(Assets::MAP_EXTENSION) => file_name,
+                _ => continue
             };
+            println!("Loading map {}", name);
+            let data = match vfs.read(&name) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("Error loading map {}: {}", name, e);
+                    continue;
+                }
+            };
+            let map_name = file_name.trim_end_matches(Assets::MAP_EXTENSION);
+            match Board::parse_map(map_name, &String::from_utf8_lossy(&data)) {
+                Ok(board) => self.maps.push(board),
+                Err(e) => println!("Error loading map {}: {}", name, e)
+            }
+        }
 
-            self.fonts.push(font);
+        // The campaign is optional, without it the game only has free play
+        let name = entry_name(CATEGORY_CFG, Assets::CAMPAIGN_FILE);
+        if vfs.contains(&name) {
+            println!("Loading campaign {}", name);
+            let data = vfs.read(&name)?;
+            match Campaign::parse(&String::from_utf8_lossy(&data)) {
+                Ok(levels) => self.campaign = levels,
+                Err(e) => println!("Error loading campaign {}: {}", name, e)
+            }
+        }
+
+        // Only loose files are edited while the game runs
+        if source == AssetSource::Loose {
+            println!("Watching {} for changes", Assets::ASSETS_DIR);
+            self.watcher = Some(AssetWatcher::new(vfs));
         }
 
         println!("Asset loaded.");
+        Ok(())
     }
 
-    async fn add_texture(&mut self, filename: &str) {
-        let result = load_texture(filename).await;
-        match result {
-            Ok(texture) => {
-                texture.set_filter(FilterMode::Nearest);
-                self.textures.push(texture);
-            },
-            Err(_e) => {
-                panic!("Error loading texture: {}", filename);
+    // Swaps changed textures, sounds and fonts in place, ids stay the same.
+    // A file that fails to decode (e.g. saved halfway) keeps the old asset.
+    pub async fn reload_changed(&mut self) {
+        let changed = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return
+        };
+
+        for (category, index, name, data) in changed {
+            println!("Reloading {}", name);
+            match category {
+                CATEGORY_IMG => {
+                    match decode_texture(&data) {
+                        Ok(texture) => {
+                            self.textures[index].delete();
+                            self.textures[index] = texture;
+                        },
+                        Err(e) => println!("Error reloading image {}: {}", name, e)
+                    }
+                },
+                // macroquad 0.3 cannot free a sound, the old one is only
+                // stopped. The watcher leaves out saves that change nothing.
+                CATEGORY_SND => {
+                    match decode_sound(&data).await {
+                        Ok(sound) => {
+                            stop_sound(self.sounds[index]);
+                            self.sounds[index] = sound;
+                        },
+                        Err(e) => println!("Error reloading sound {}: {}", name, e)
+                    }
+                },
+                CATEGORY_FNT => {
+                    match load_ttf_font_from_bytes(&data) {
+                        Ok(font) => self.fonts[index] = font,
+                        Err(_e) => println!("Error reloading font: {}", name)
+                    }
+                },
+                _ => { }
             }
         }
     }
 
-    async fn add_sound(&mut self, filename: &str) {
-        let result = load_sound(filename).await;
-        match result {
-            Ok(sound) => {
-               self.sounds.push(sound);
+    // Mount order, lowest priority first: data.pak (or the loose assets/ 
+    // directory), patch.pak, mods/*.pak in name order, then loose files under 
+    // mods/ (e.g. mods/images/wall.png)
+    fn mount(source: AssetSource) -> PakResult<VirtualFs> {
+        // Encrypted paks need the game to be built with the same DATAPAK_KEY
+        let key = match option_env!("DATAPAK_KEY") {
+            Some(key) => Some(parse_key(key).ok_or(PakError::BadKey)?),
+            None => None
+        };
+
+        let mut vfs = VirtualFs::new();
+
+        match source {
+            AssetSource::Pak => {
+                let mut base = match key {
+                    Some(key) => DataPakLoader::with_key(Assets::BASE_PAK, key)?,
+                    None => DataPakLoader::new(Assets::BASE_PAK)?
+                };
+
+                // v1 paks store no names, so their entries are named by position
+                if !base.has_names() {
+                    base.assign_names(CATEGORY_IMG, 
+                                      &entry_names(CATEGORY_IMG, &Assets::TEXTURE_FILES))?;
+                    base.assign_names(CATEGORY_SND, 
+                                      &entry_names(CATEGORY_SND, &Assets::SOUND_FILES))?;
+                    base.assign_names(CATEGORY_FNT, 
+                                      &entry_names(CATEGORY_FNT, &Assets::FONT_FILES))?;
+                }
+                vfs.mount_loader(base);
             },
-            Err(_e) => {
-                panic!("Error loading sound: {}", filename);
-            }
+            AssetSource::Loose => vfs.mount_dir(Assets::ASSETS_DIR)
         }
-    }
 
-    async fn add_font(&mut self, filename: &str) {
-        let result = load_ttf_font(filename).await;
-        match result {
-            Ok(font) => {
-               self.fonts.push(font);
-            },
-            Err(_e) => {
-                panic!("Error loading font: {}", filename);
+        // Only the base assets are needed, a broken patch or mod is left out
+        if Path::new(Assets::PATCH_PAK).is_file() {
+            mount_optional_pak(&mut vfs, Assets::PATCH_PAK, key);
+        }
+
+        if Path::new(Assets::MODS_DIR).is_dir() {
+            let mut mod_paks: Vec<PathBuf> = match fs::read_dir(Assets::MODS_DIR) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension() == Some("pak".as_ref()))
+                    .collect(),
+                Err(err) => {
+                    println!("Skipping mod paks in {}: {}", Assets::MODS_DIR, err);
+                    Vec::new()
+                }
+            };
+            mod_paks.sort();
+
+            for mod_pak in mod_paks {
+                mount_optional_pak(&mut vfs, &mod_pak.to_string_lossy(), key);
             }
+            vfs.mount_dir(Assets::MODS_DIR);
         }
+
+        Ok(vfs)
+    }
+
+    fn add_texture(&mut self, name: &str, data: &[u8]) -> PakResult<()> {
+        let texture = decode_texture(data).map_err(|reason| bad_entry_data(name, reason))?;
+        self.textures.push(texture);
+        Ok(())
+    }
+
+    async fn add_sound(&mut self, name: &str, data: &[u8]) -> PakResult<()> {
+        let sound = decode_sound(data).await.map_err(|reason| bad_entry_data(name, reason))?;
+        self.sounds.push(sound);
+        Ok(())
+    }
+
+    fn add_font(&mut self, name: &str, data: &[u8]) -> PakResult<()> {
+        let font = load_ttf_font_from_bytes(data)
+            .map_err(|e| bad_entry_data(name, format!("{:?}", e)))?;
+        self.fonts.push(font);
+        Ok(())
     }
 
     pub fn play_sound(&self, id: usize) {
@@ -134,4 +285,150 @@ impl Assets {
         let font: &Font = self.fonts.get(id).unwrap();
         &font
     }
+
+    pub fn maps(&self) -> &[Board] {
+        &self.maps
+    }
+
+    pub fn campaign(&self) -> &[CampaignLevel] {
+        &self.campaign
+    }
+}
+
+//=============================================================================
+//    AssetWatcher
+//=============================================================================
+// Polls the modification times of the loose asset files. Changed files are
+// read back through the mounts, so a file overridden by a mod stays overridden,
+// and only handed on when their data is new.
+struct WatchedFile {
+    category: usize,
+    index: usize,
+    name: String,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    hash: Option<u64>  // Of the data last loaded
+}
+
+struct AssetWatcher {
+    vfs: VirtualFs,
+    files: Vec<WatchedFile>,
+    timer: Timer
+}
+
+impl AssetWatcher {
+    const POLL_INTERVAL: f32 = 0.5;
+
+    fn new(mut vfs: VirtualFs) -> AssetWatcher {
+        let mut files = Vec::new();
+        let tables: [(usize, &[&str]); 3] = [
+            (CATEGORY_IMG, &Assets::TEXTURE_FILES),
+            (CATEGORY_SND, &Assets::SOUND_FILES),
+            (CATEGORY_FNT, &Assets::FONT_FILES)
+        ];
+
+        for (category, table) in tables.iter() {
+            for (index, file) in table.iter().enumerate() {
+                let name = entry_name(*category, file);
+                let path = Path::new(Assets::ASSETS_DIR).join(&name);
+                let modified = modified_time(&path);
+                let hash = vfs.read(&name).ok().map(|data| data_hash(&data));
+                files.push(WatchedFile { category: *category, index, name, path, modified, hash });
+            }
+        }
+
+        AssetWatcher { vfs, files, timer: Timer::new(AssetWatcher::POLL_INTERVAL) }
+    }
+
+    // Returns (category, index, name, data) for every file changed since the last poll
+    fn poll(&mut self) -> Vec<(usize, usize, String, Vec<u8>)> {
+        let mut changed = Vec::new();
+        if !self.timer.update() {
+            return changed;
+        }
+        self.timer.reset();
+
+        for file in self.files.iter_mut() {
+            let modified = modified_time(&file.path);
+            if modified == file.modified {
+                continue;
+            }
+            file.modified = modified;
+
+            match self.vfs.read(&file.name) {
+                Ok(data) => {
+                    let hash = Some(data_hash(&data));
+                    if hash != file.hash {
+                        file.hash = hash;
+                        changed.push((file.category, file.index, file.name.clone(), data));
+                    }
+                },
+                Err(e) => println!("Error reloading {}: {}", file.name, e)
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn data_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn entry_names(category: usize, files: &[&str]) -> Vec<String> {
+    files.iter().map(|file| entry_name(category, file)).collect()
+}
+
+fn bad_entry_data(name: &str, reason: String) -> PakError {
+    PakError::BadEntryData { name: name.to_string(), reason }
+}
+
+fn mount_optional_pak(vfs: &mut VirtualFs, file_name: &str, key: Option<PakKey>) {
+    if let Err(err) = vfs.mount_pak(file_name, key) {
+        println!("Skipping {}: {}", file_name, err);
+    }
+}
+
+// macroquad panics on image and sound data it cannot decode, so the data
+// is decoded or checked here first
+fn decode_texture(data: &[u8]) -> Result<Texture2D, String> {
+    let image = image::load_from_memory_with_format(data, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?
+        .to_rgba8();
+    if image.width() > u16::MAX as u32 || image.height() > u16::MAX as u32 {
+        return Err(format!("image is too large ({}x{})", image.width(), image.height()));
+    }
+    let texture = Texture2D::from_rgba8(image.width() as u16, image.height() as u16, image.as_raw());
+    texture.set_filter(FilterMode::Nearest);
+    Ok(texture)
+}
+
+async fn decode_sound(data: &[u8]) -> Result<Sound, String> {
+    check_wav(data)?;
+    load_sound_from_bytes(data).await.map_err(|e| e.to_string())
+}
+
+// Reads every sample, as the mixer does on loading, and checks for the mono
+// or stereo it plays
+fn check_wav(data: &[u8]) -> Result<(), String> {
+    let mut reader = hound::WavReader::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    if spec.channels != 1 && spec.channels != 2 {
+        return Err(format!("{} channels, only mono and stereo can be played", spec.channels));
+    }
+    if spec.sample_rate == 0 {
+        return Err("sample rate is 0".to_string());
+    }
+
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().try_for_each(|sample| sample.map(drop)),
+        hound::SampleFormat::Int => reader.samples::<i32>().try_for_each(|sample| sample.map(drop))
+    };
+    samples.map_err(|e| e.to_string())
 }
@@ -0,0 +1,356 @@
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::sim::*;
+
+//=============================================================================
+//    BotController
+//=============================================================================
+// Lets a program control a snake over a local TCP connection, one JSON
+// object per line. Player 1's bot connects to the base port, player 2's to
+// the base port + 1 and so on. Every tick the game sends the state the next step
+// starts from:
+//   {"tick":812,"state":"playing","you":0,"width":23,"height":20,"wrap":false,
+//    "snakes":[{"id":0,"alive":true,"direction":"up","score":48,
+//               "body":[[12,7],[12,8],[12,9]]}, ...],
+//    "apples":[[3,4],[17,12]],"walls":[[8,6],[8,7]]}
+// and the bot answers with the tick it is replying to and a direction
+// ("up", "down", "left", "right" or null to keep going):
+//   {"tick":812,"direction":"left"}
+// The game never waits for a bot: the socket is written and read on threads
+// of their own, and an answer is used on the first tick after it arrives,
+// as long as it is not older than the timeout. Until then the snake keeps
+// its direction. A bot that reads slower than the game ticks misses states
+// rather than falling behind. "walls" lists the walls inside the outer wall, which maps add.
+// With "wrap" the outer wall is gone and the snakes come back in at the
+// opposite edge.
+pub struct BotController {
+    listener: TcpListener,
+    connection: Option<BotConnection>,
+    max_age: u64  // Ticks an answer stays good for
+}
+
+struct BotConnection {
+    states: SyncSender<String>,
+    replies: Receiver<Reply>
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    tick: u64,
+    direction: Option<String>
+}
+
+impl BotController {
+    pub const DEFAULT_PORT: u16 = 7878;
+    pub const DEFAULT_TIMEOUT_MS: u64 = 50;
+
+    pub fn listen(port: u16, timeout: Duration) -> io::Result<BotController> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        println!("Waiting for a bot on 127.0.0.1:{}", port);
+        let max_age = (timeout.as_millis() as u64 * TICK_RATE as u64).div_ceil(1000);
+        Ok(BotController { listener, connection: None, max_age: max_age.max(1) })
+    }
+
+    fn accept(&mut self, id: usize) {
+        match self.listener.accept() {
+            Ok((stream, address)) => {
+                println!("Bot for player {} connected from {}", id + 1, address);
+                match BotConnection::new(stream, id) {
+                    Ok(connection) => self.connection = Some(connection),
+                    Err(e) => println!("Error setting up bot connection: {}", e)
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => { },
+            Err(e) => println!("Error accepting bot connection: {}", e)
+        }
+    }
+}
+
+impl Controller for BotController {
+    fn next_direction(&mut self, world: &World, id: usize) -> Option<Direction> {
+        if self.connection.is_none() {
+            self.accept(id);
+        }
+
+        let connection = self.connection.as_mut()?;
+        match connection.exchange(world, id, self.max_age) {
+            Some(direction) => direction,
+            None => {
+                println!("Bot for player {} disconnected", id + 1);
+                self.connection = None;
+                None
+            }
+        }
+    }
+}
+
+impl BotConnection {
+    fn new(stream: TcpStream, id: usize) -> io::Result<BotConnection> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        // Both threads end when the connection or the other side of their
+        // channel goes. One state waits while the last one is written.
+        let (states, state_receiver) = mpsc::sync_channel::<String>(1);
+        thread::spawn(move || {
+            for message in state_receiver {
+                if writer.write_all(message.as_bytes()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (reply_sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        println!("Error reading from bot for player {}: {}", id + 1, e);
+                        break;
+                    }
+                };
+                match parse_reply(&line) {
+                    Ok(reply) => {
+                        if reply_sender.send(reply).is_err() {
+                            break;
+                        }
+                    },
+                    Err(e) => println!("Ignoring bot message ({}): {}", e, line.trim())
+                }
+            }
+        });
+
+        Ok(BotConnection { states, replies })
+    }
+
+    // Takes the answers that have arrived since the last tick and sends the
+    // state for this one, None once the connection is gone. The newest answer
+    // that is not too old wins.
+    fn exchange(&mut self, world: &World, id: usize, max_age: u64)
+            -> Option<Option<Direction>> {
+        let tick = world.tick_count();
+        let mut direction = None;
+        loop {
+            match self.replies.try_recv() {
+                Ok(reply) => {
+                    if let Some(dir) = reply_direction(&reply, tick, max_age) {
+                        direction = Some(dir);
+                    }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return None
+            }
+        }
+
+        let mut message = state_json(world, id);
+        message.push('\n');
+        match self.states.try_send(message) {
+            Ok(()) | Err(TrySendError::Full(_)) => Some(direction),
+            Err(TrySendError::Disconnected(_)) => None
+        }
+    }
+}
+
+//=============================================================================
+//  JSON
+//=============================================================================
+#[derive(Serialize)]
+struct StateMessage {
+    tick: u64,
+    state: &'static str,
+    you: usize,
+    width: i32,
+    height: i32,
+    wrap: bool,
+    snakes: Vec<SnakeState>,
+    apples: Vec<[i32; 2]>,
+    walls: Vec<[i32; 2]>
+}
+
+#[derive(Serialize)]
+struct SnakeState {
+    id: usize,
+    alive: bool,
+    direction: Option<&'static str>,
+    score: i32,
+    body: Vec<[i32; 2]>
+}
+
+fn parse_reply(line: &str) -> serde_json::Result<Reply> {
+    serde_json::from_str(line)
+}
+
+// The direction of an answer to the given tick or one at most max_age before it
+fn reply_direction(reply: &Reply, tick: u64, max_age: u64) -> Option<Direction> {
+    if reply.tick > tick || tick - reply.tick > max_age {
+        return None;
+    }
+    let name = reply.direction.as_ref()?;
+    let dir = direction_from_name(name);
+    if dir.is_none() {
+        println!("Ignoring bot direction: {}", name);
+    }
+    dir
+}
+
+fn state_json(world: &World, id: usize) -> String {
+    let board = world.board();
+    let snakes = world.snakes().iter().enumerate()
+        .map(|(i, snake)| SnakeState {
+            id: i,
+            alive: snake.is_alive(),
+            direction: (snake.length() > 0).then(|| direction_name(snake.direction())),
+            score: world.score(i),
+            body: snake.parts().map(|part| [part.pos.x, part.pos.y]).collect()
+        })
+        .collect();
+    let apples = world.apples().iter()
+        .map(|apple| [apple.pos.x, apple.pos.y])
+        .collect();
+    let mut walls = Vec::new();
+    for y in 1..board.height() - 1 {
+        for x in 1..board.width() - 1 {
+            if board.is_wall(&Pos::new(x, y)) {
+                walls.push([x, y]);
+            }
+        }
+    }
+
+    let message = StateMessage {
+        tick: world.tick_count(),
+        state: state_name(world.state()),
+        you: id,
+        width: board.width(),
+        height: board.height(),
+        wrap: board.wraps(),
+        snakes,
+        apples,
+        walls
+    };
+    serde_json::to_string(&message).expect("bot state is always valid JSON")
+}
+
+fn state_name(state: LevelState) -> &'static str {
+    match state {
+        LevelState::GetReady => "get_ready",
+        LevelState::SnakeEntering => "entering",
+        LevelState::Playing => "playing",
+        LevelState::Exiting => "exiting",
+        LevelState::LevelComplete => "level_complete",
+        LevelState::Stunned => "stunned",
+        LevelState::Dying => "dying",
+        LevelState::GameOver => "game_over"
+    }
+}
+
+fn direction_name(dir: Direction) -> &'static str {
+    match dir {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right"
+    }
+}
+
+fn direction_from_name(name: &str) -> Option<Direction> {
+    match name {
+        "up" => Some(Direction::Up),
+        "down" => Some(Direction::Down),
+        "left" => Some(Direction::Left),
+        "right" => Some(Direction::Right),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const MAP: &str = "\
+#######
+#.....#
+#..#..#
+#.....#
+#.....#
+#1###2#
+";
+
+    fn state(world: &World, id: usize) -> Value {
+        serde_json::from_str(&state_json(world, id)).unwrap()
+    }
+
+    #[test]
+    fn state_describes_the_world() {
+        let board = Board::parse_map("Test", MAP).unwrap();
+        let mut world = World::with_board(board, 1);
+        world.reset(2, 1);
+
+        let message = state(&world, 1);
+        assert_eq!(message["tick"], 0);
+        assert_eq!(message["state"], "get_ready");
+        assert_eq!(message["you"], 1);
+        assert_eq!((message["width"].clone(), message["height"].clone()), (json!(7), json!(6)));
+        assert_eq!(message["wrap"], false);
+        assert_eq!(message["walls"], json!([[3, 2]]));
+        assert_eq!(message["apples"], json!([]));
+
+        let snakes = message["snakes"].as_array().unwrap();
+        assert_eq!(snakes.len(), world.snakes().len());
+        for (i, snake) in snakes.iter().enumerate() {
+            assert_eq!(snake["id"], i);
+            assert_eq!(snake["alive"], world.snake(i).is_alive());
+            assert_eq!(snake["score"], 0);
+            assert_eq!(snake["body"].as_array().unwrap().len(), world.snake(i).length() as usize);
+        }
+
+        while world.state() != LevelState::Playing {
+            world.step(&[]);
+        }
+        let message = state(&world, 0);
+        assert_eq!(message["state"], "playing");
+        assert_eq!(message["tick"], world.tick_count());
+        assert_eq!(message["snakes"][0]["direction"], "up");
+        let head = world.snake(0).position();
+        assert_eq!(message["snakes"][0]["body"][0], json!([head.x, head.y]));
+    }
+
+    #[test]
+    fn parses_replies() {
+        let reply = parse_reply(r#"{"tick":812,"direction":"left"}"#).unwrap();
+        assert_eq!(reply.tick, 812);
+        assert_eq!(reply.direction.as_deref(), Some("left"));
+        assert_eq!(parse_reply(r#"{"tick":3,"direction":null}"#).unwrap().direction, None);
+        assert_eq!(parse_reply(r#"{"tick":3}"#).unwrap().direction, None);
+        assert_eq!(parse_reply(r#" {"direction":"up", "tick":4, "note":"hi"} "#).unwrap().tick, 4);
+
+        assert!(parse_reply(r#"{"direction":"up"}"#).is_err());
+        assert!(parse_reply(r#"{"tick":-1,"direction":"up"}"#).is_err());
+        assert!(parse_reply(r#"{"tick":3,"direction":1}"#).is_err());
+        assert!(parse_reply("left").is_err());
+        assert!(parse_reply("").is_err());
+    }
+
+    #[test]
+    fn only_recent_replies_turn_the_snake() {
+        let reply = |tick: u64, direction: Option<&str>| Reply { 
+            tick, 
+            direction: direction.map(String::from) 
+        };
+        assert_eq!(reply_direction(&reply(10, Some("down")), 10, 3), Some(Direction::Down));
+        assert_eq!(reply_direction(&reply(7, Some("right")), 10, 3), Some(Direction::Right));
+        assert_eq!(reply_direction(&reply(6, Some("right")), 10, 3), None);
+        assert_eq!(reply_direction(&reply(11, Some("up")), 10, 3), None);
+        assert_eq!(reply_direction(&reply(10, None), 10, 3), None);
+        assert_eq!(reply_direction(&reply(10, Some("north")), 10, 3), None);
+    }
+}
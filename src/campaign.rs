@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+//=============================================================================
+//    CampaignLevel (configs/campaign.toml)
+//=============================================================================
+// The levels in the order they are played, e.g.
+//   [[level]]
+//   map = "pillars"  # a map from maps/, the plain arena when left out
+//   goal = 10        # apples to eat before the gates open
+//   speed = 3.5      # cells per second at the start
+#[derive(Deserialize, Clone, Debug)]
+pub struct CampaignLevel {
+    #[serde(default)]
+    pub map: String,
+    pub goal: u32,
+    pub speed: f32
+}
+
+#[derive(Deserialize)]
+struct CampaignFile {
+    level: Vec<CampaignLevel>
+}
+
+// What is kept between sessions, in progress.toml next to the game. The level
+// is kept by its map, levels whose map is missing are left out of the campaign
+// and would shift any index.
+#[derive(Serialize, Deserialize, Default)]
+struct Progress {
+    #[serde(default)]
+    map: String,
+    score: i32
+}
+
+//=============================================================================
+//    Campaign
+//=============================================================================
+// One level after the other: the gates open once the level's apples are
+// eaten, and leaving through one moves on to the next level. A game over
+// only restarts the level, the levels before it stay done.
+pub struct Campaign {
+    levels: Vec<CampaignLevel>,
+    level: usize,
+    score: i32,  // Of the levels completed
+    finished: bool,
+    progress_file: PathBuf
+}
+
+impl Campaign {
+    const PROGRESS_FILE: &'static str = "progress.toml";
+
+    pub fn parse(text: &str) -> Result<Vec<CampaignLevel>, toml::de::Error> {
+        let file: CampaignFile = toml::from_str(text)?;
+        Ok(file.level)
+    }
+
+    pub fn new(levels: Vec<CampaignLevel>) -> Campaign {
+        Campaign::with_progress_file(levels, PathBuf::from(Campaign::PROGRESS_FILE))
+    }
+
+    // Picks up at the level the last session got to, the first one on its map
+    fn with_progress_file(levels: Vec<CampaignLevel>, progress_file: PathBuf) -> Campaign {
+        let progress = read_progress(&progress_file);
+        let mut campaign = Campaign { levels, level: 0, score: 0, finished: false, progress_file };
+        if let Some(level) = campaign.levels.iter().position(|level| level.map == progress.map) {
+            campaign.level = level;
+            campaign.score = progress.score;
+        }
+        campaign
+    }
+
+    pub fn level(&self) -> &CampaignLevel {
+        &self.levels[self.level]
+    }
+
+    // Counted from 1
+    pub fn level_number(&self) -> usize {
+        self.level + 1
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    // The last level is done
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Moves on to the next level and saves the progress. After the last
+    // level a new session starts over from the first.
+    pub fn complete_level(&mut self, score: i32) {
+        self.score += score;
+        if self.level + 1 < self.levels.len() {
+            self.level += 1;
+            self.save_progress(&self.levels[self.level].map, self.score);
+        } else {
+            self.finished = true;
+            self.save_progress(&self.levels[0].map, 0);
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.level = 0;
+        self.score = 0;
+        self.finished = false;
+    }
+
+//=============================================================================
+//  Private methods (Campaign)
+//=============================================================================
+    // A failure only costs the progress
+    fn save_progress(&self, map: &str, score: i32) {
+        let result = toml::to_string(&Progress { map: map.to_string(), score })
+            .map_err(|e| e.to_string())
+            .and_then(|text| fs::write(&self.progress_file, text).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Progress saved to {}", self.progress_file.display()),
+            Err(e) => println!("Error saving {}: {}", self.progress_file.display(), e)
+        }
+    }
+}
+
+fn read_progress(progress_file: &Path) -> Progress {
+    let text = match fs::read_to_string(progress_file) {
+        Ok(text) => text,
+        Err(_) => return Progress::default()
+    };
+
+    match toml::from_str(&text) {
+        Ok(progress) => progress,
+        Err(e) => {
+            println!("Ignoring {}: {}", progress_file.display(), e);
+            Progress::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMPAIGN: &str = r#"
+[[level]]
+goal = 5
+speed = 3.0
+
+[[level]]
+map = "pillars"
+goal = 10
+speed = 3.5
+
+[[level]]
+map = "cross"
+goal = 15
+speed = 4.0
+"#;
+
+    fn progress_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("snake_test_{}_{}.toml", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn maps(levels: &[CampaignLevel]) -> Vec<&str> {
+        levels.iter().map(|level| level.map.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_levels() {
+        let levels = Campaign::parse(CAMPAIGN).unwrap();
+        assert_eq!(maps(&levels), vec!["", "pillars", "cross"]);
+        assert_eq!(levels[1].goal, 10);
+        assert_eq!(levels[2].speed, 4.0);
+        assert!(Campaign::parse("[[level]]\nmap = \"cross\"\n").is_err());
+    }
+
+    #[test]
+    fn next_level_opens_once_the_last_one_is_done() {
+        let path = progress_file("unlock");
+        let levels = Campaign::parse(CAMPAIGN).unwrap();
+
+        let mut campaign = Campaign::with_progress_file(levels.clone(), path.clone());
+        assert_eq!(campaign.level_number(), 1);
+        campaign.complete_level(40);
+        assert_eq!(campaign.level_number(), 2);
+        assert_eq!(campaign.level().map, "pillars");
+
+        // The next session starts at the level reached, not before
+        let mut campaign = Campaign::with_progress_file(levels.clone(), path.clone());
+        assert_eq!(campaign.level_number(), 2);
+        assert_eq!(campaign.score(), 40);
+        campaign.complete_level(60);
+        assert_eq!(campaign.level().map, "cross");
+        assert_eq!(campaign.score(), 100);
+
+        // After the last level the campaign is over and starts over next time
+        campaign.complete_level(10);
+        assert!(campaign.is_finished());
+        let campaign = Campaign::with_progress_file(levels, path.clone());
+        assert_eq!(campaign.level_number(), 1);
+        assert_eq!(campaign.score(), 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn progress_is_kept_by_map() {
+        let path = progress_file("by_map");
+        let levels = Campaign::parse(CAMPAIGN).unwrap();
+        let mut campaign = Campaign::with_progress_file(levels.clone(), path.clone());
+        campaign.complete_level(10);
+        campaign.complete_level(20);
+        assert_eq!(campaign.level().map, "cross");
+
+        // Without the pillars map its level is left out, and "cross" moves up
+        let without_pillars: Vec<CampaignLevel> = levels.iter()
+            .filter(|level| level.map != "pillars")
+            .cloned()
+            .collect();
+        let campaign = Campaign::with_progress_file(without_pillars, path.clone());
+        assert_eq!(campaign.level_number(), 2);
+        assert_eq!(campaign.level().map, "cross");
+        assert_eq!(campaign.score(), 30);
+
+        // A map that is gone altogether starts the campaign from the beginning
+        fs::write(&path, "map = \"spiral\"\nscore = 50\n").unwrap();
+        let campaign = Campaign::with_progress_file(levels, path.clone());
+        assert_eq!(campaign.level_number(), 1);
+        assert_eq!(campaign.score(), 0);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use macroquad::prelude::*;
+use crate::sim::Pos;
 
 //=============================================================================
 //    Timer
@@ -14,11 +15,6 @@ impl Timer {
         Timer { duration, counter }
     }
 
-    pub fn set(&mut self, duration: f32) {
-        self.duration = duration;
-        self.counter = 0.0;
-    }
-
     pub fn reset(&mut self) {
         self.counter = 0.0;
     }
@@ -52,33 +48,11 @@ impl Line {
     }
 }
 
-//=============================================================================
-//    Direction
-//=============================================================================
-#[derive(Copy, Clone, PartialEq)]
-pub enum Direction {
-    Up, Down, Left, Right 
-}
-
-pub fn dir_to_vec2(dir: Direction) -> Vec2 {
-    match dir {
-        Direction::Up => Vec2::new(0.0, -1.0), 
-        Direction::Down => Vec2::new(0.0, 1.0), 
-        Direction::Left => Vec2::new(-1.0, 0.0), 
-        Direction::Right => Vec2::new(1.0, 0.0) 
-    }
-}
-
-pub fn opposite_dir(dir: Direction) -> Direction {
-    match dir {
-        Direction::Up => Direction::Down,
-        Direction::Down => Direction::Up, 
-        Direction::Left =>  Direction::Right,
-        Direction::Right => Direction::Left,
-    }
+// Board cells to the float coordinates GameScene draws with
+pub fn pos_to_vec2(pos: Pos) -> Vec2 {
+    Vec2::new(pos.x as f32, pos.y as f32)
 }
 
-
 pub fn text_center_pos(text: &str, text_params: TextParams,
                        rect: &Rect) -> Vec2 {
     let dimension: TextDimensions = 
@@ -0,0 +1,337 @@
+use std::fs;
+use macroquad::prelude::*;
+use crate::gamescene::*;
+use crate::label::*;
+use crate::common::*;
+use crate::sim::*;
+
+//=============================================================================
+//    Editor
+//=============================================================================
+// Paints a map on the scene's grid with the mouse. The map is kept as the
+// tiles of the map format, so whatever is painted can be saved as it is,
+// and Board::parse_map checks it before it is played or saved.
+//   F1 wall, F2 floor, F3 apple zone, F4 gate
+//   Left mouse button paints, right button clears
+//   Letters, digits and space type the name, Backspace deletes
+//   Enter test-plays the map, Ctrl+S saves it to mods/maps/<name>.map
+//   Escape leaves the editor
+// Gates go in the outer wall and are numbered in the order they are placed.
+// Player N spawns in front of gate N.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Tool {
+    Wall, Floor, AppleZone, Gate
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Wall => "Wall",
+            Tool::Floor => "Floor",
+            Tool::AppleZone => "Apples",
+            Tool::Gate => "Gate"
+        }
+    }
+}
+
+// What the game has to do after the editor's input
+pub enum EditorCommand {
+    None,
+    TestPlay(Board),
+    Saved(Board),
+    Quit
+}
+
+pub struct Editor {
+    name: String,
+    width: i32,
+    height: i32,
+    tiles: Vec<char>,
+    tool: Tool,
+    cursor: Option<Pos>,  // The cell under the mouse
+    message: String,
+    label_title: Label,
+    label_name: Label,
+    labels_tool: Vec<Label>,
+    label_message: Label,
+    label_help: Label
+}
+
+impl Editor {
+    // Mounted on top of data.pak and of loose assets alike
+    const MAP_DIR: &'static str = "mods/maps";
+    const MAX_NAME_LENGTH: usize = 20;
+    const TOOLS: [Tool; 4] = [Tool::Wall, Tool::Floor, Tool::AppleZone, Tool::Gate];
+
+    // Starts from the board being played, the built-in arena becomes a new map
+    pub fn new(board: &Board) -> Editor {
+        let name = if board.name().is_empty() { 
+            String::from("Custom") 
+        } else { 
+            board.name().to_string() 
+        };
+        let tiles = board.to_map_text().lines()
+            .filter(|line| !line.contains('='))
+            .flat_map(|line| line.chars())
+            .collect();
+
+        Editor {
+            name,
+            width: board.width(),
+            height: board.height(),
+            tiles,
+            tool: Tool::Wall,
+            cursor: None,
+            message: String::new(),
+            label_title: Label::new(),
+            label_name: Label::new(),
+            labels_tool: Editor::TOOLS.iter().map(|_| Label::new()).collect(),
+            label_message: Label::new(),
+            label_help: Label::new()
+        }
+    }
+
+    // mouse is in the pixels of the scene
+    pub fn handle_input(&mut self, mouse: Vec2, scene: &GameScene) -> EditorCommand {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+
+        while let Some(c) = get_char_pressed() {
+            if ctrl || !(c.is_ascii_alphanumeric() || c == ' ') {
+                continue;
+            }
+            if self.name.len() < Editor::MAX_NAME_LENGTH {
+                self.name.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.name.pop();
+        }
+
+        if is_key_pressed(KeyCode::F1) {
+            self.tool = Tool::Wall;
+        } else if is_key_pressed(KeyCode::F2) {
+            self.tool = Tool::Floor;
+        } else if is_key_pressed(KeyCode::F3) {
+            self.tool = Tool::AppleZone;
+        } else if is_key_pressed(KeyCode::F4) {
+            self.tool = Tool::Gate;
+        }
+
+        let cell = Pos::new((mouse.x / scene.grid_size()).floor() as i32,
+                            (mouse.y / scene.grid_size()).floor() as i32);
+        self.cursor = if self.contains(&cell) { Some(cell) } else { None };
+        if self.contains(&cell) {
+            // Gates are placed one click at a time, so dragging does not number them all
+            if self.tool == Tool::Gate {
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    self.place_gate(&cell);
+                }
+            } else if is_mouse_button_down(MouseButton::Left) {
+                self.paint(&cell, self.tool);
+            }
+            if is_mouse_button_down(MouseButton::Right) {
+                self.clear(&cell);
+            }
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            EditorCommand::Quit
+        } else if is_key_pressed(KeyCode::Enter) {
+            match self.board() {
+                Some(board) => EditorCommand::TestPlay(board),
+                None => EditorCommand::None
+            }
+        } else if ctrl && is_key_pressed(KeyCode::S) {
+            match self.board() {
+                Some(board) => {
+                    self.save();
+                    EditorCommand::Saved(board)
+                },
+                None => EditorCommand::None
+            }
+        } else {
+            EditorCommand::None
+        }
+    }
+
+    pub fn draw(&mut self, scene: &GameScene, font: &Font, status_panel: &Rect) {
+        clear_background(BLACK);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pos = Pos::new(x, y);
+                let tile = self.tile(&pos);
+                let color = match tile {
+                    TILE_WALL => BLUE,
+                    TILE_APPLE_ZONE => DARKGREEN,
+                    '1'..='4' => ORANGE,
+                    _ => continue
+                };
+                scene.draw_block(&pos_to_vec2(pos), &color);
+                if tile.is_ascii_digit() {
+                    let size = scene.grid_size();
+                    draw_text(&tile.to_string(), (x as f32 + 0.25) * size, (y as f32 + 0.75) * size, 
+                              size, BLACK);
+                }
+            }
+        }
+
+        // Where the mouse paints
+        if let Some(cursor) = self.cursor {
+            let size = scene.grid_size();
+            draw_rectangle_lines(cursor.x as f32 * size, cursor.y as f32 * size, size, size, 2.0, WHITE);
+        }
+
+        let mut top = 40.0;
+        self.label_title
+            .set_font(font, 34)
+            .set_color(&WHITE)
+            .set_text("Editor")
+            .center(None, Some(top), status_panel)
+            .draw();
+        top = self.label_title.bottom() + 20.0;
+
+        self.label_name
+            .set_font(font, 26)
+            .set_color(&YELLOW)
+            .set_text(&format!("{}_", self.name))
+            .center(None, Some(top), status_panel)
+            .draw();
+        top = self.label_name.bottom() + 30.0;
+
+        for (i, tool) in Editor::TOOLS.iter().enumerate() {
+            let color = if *tool == self.tool { WHITE } else { GRAY };
+            self.labels_tool[i]
+                .set_font(font, 24)
+                .set_color(&color)
+                .set_text(&format!("F{} {}", i + 1, tool.name()))
+                .center(None, Some(top), status_panel)
+                .draw();
+            top = self.labels_tool[i].bottom() + 10.0;
+        }
+
+        self.label_help
+            .set_font(font, 18)
+            .set_color(&GRAY)
+            .set_text("Enter test  Ctrl+S save")
+            .center(None, Some(top + 20.0), status_panel)
+            .draw();
+
+        self.label_message
+            .set_font(font, 18)
+            .set_color(&WHITE)
+            .set_text(&self.message)
+            .center(None, Some(status_panel.bottom() - 60.0), status_panel)
+            .draw();
+    }
+
+//=============================================================================
+//  Private methods (Editor)
+//=============================================================================
+    fn contains(&self, pos: &Pos) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height
+    }
+
+    fn is_outer_wall(&self, pos: &Pos) -> bool {
+        pos.x == 0 || pos.y == 0 || pos.x == self.width - 1 || pos.y == self.height - 1
+    }
+
+    fn tile(&self, pos: &Pos) -> char {
+        self.tiles[(pos.y * self.width + pos.x) as usize]
+    }
+
+    fn set_tile(&mut self, pos: &Pos, tile: char) {
+        self.tiles[(pos.y * self.width + pos.x) as usize] = tile;
+    }
+
+    // Walls, floor and apples only go inside the outer wall
+    fn paint(&mut self, pos: &Pos, tool: Tool) {
+        if self.is_outer_wall(pos) {
+            return;
+        }
+        match tool {
+            Tool::Wall => self.set_tile(pos, TILE_WALL),
+            Tool::Floor => self.set_tile(pos, TILE_FLOOR),
+            Tool::AppleZone => self.set_tile(pos, TILE_APPLE_ZONE),
+            Tool::Gate => { }
+        }
+    }
+
+    // A removed gate leaves the wall behind, the gates after it move up a number
+    fn clear(&mut self, pos: &Pos) {
+        let tile = self.tile(pos);
+        if let Some(number) = tile.to_digit(10) {
+            self.set_tile(pos, TILE_WALL);
+            for gate in self.tiles.iter_mut() {
+                match gate.to_digit(10) {
+                    Some(other) if other > number => {
+                        *gate = std::char::from_digit(other - 1, 10).unwrap();
+                    },
+                    _ => { }
+                }
+            }
+        } else if !self.is_outer_wall(pos) {
+            self.set_tile(pos, TILE_FLOOR);
+        }
+    }
+
+    fn place_gate(&mut self, pos: &Pos) {
+        let corner = (pos.x == 0 || pos.x == self.width - 1) && (pos.y == 0 || pos.y == self.height - 1);
+        if !self.is_outer_wall(pos) || corner {
+            self.message = String::from("Gates go in the outer wall");
+            return;
+        }
+        if self.tile(pos).is_ascii_digit() {
+            return;
+        }
+
+        let gate_count = self.tiles.iter().filter(|tile| tile.is_ascii_digit()).count();
+        if gate_count >= Board::MAX_GATES {
+            self.message = format!("{} gates at most", Board::MAX_GATES);
+            return;
+        }
+        self.set_tile(pos, std::char::from_digit(gate_count as u32 + 1, 10).unwrap());
+    }
+
+    fn map_text(&self) -> String {
+        let mut text = format!("name = {}\n", self.name.trim());
+        for row in self.tiles.chunks(self.width as usize) {
+            text.extend(row);
+            text.push('\n');
+        }
+        text
+    }
+
+    // The map as the game would load it, or None with the reason in the message
+    fn board(&mut self) -> Option<Board> {
+        if self.name.trim().is_empty() {
+            self.message = String::from("The map needs a name");
+            return None;
+        }
+
+        match Board::parse_map(self.name.trim(), &self.map_text()) {
+            Ok(board) => {
+                self.message.clear();
+                Some(board)
+            },
+            Err(e) => {
+                self.message = e.to_string();
+                None
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        let file_name: String = self.name.trim().chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect();
+        let path = format!("{}/{}.map", Editor::MAP_DIR, file_name);
+
+        let result = fs::create_dir_all(Editor::MAP_DIR)
+            .and_then(|_| fs::write(&path, self.map_text()));
+        self.message = match result {
+            Ok(()) => format!("Saved {}", path),
+            Err(e) => format!("Error saving {}: {}", path, e)
+        };
+        println!("{}", self.message);
+    }
+}
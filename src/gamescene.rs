@@ -1,5 +1,6 @@
 use macroquad::prelude::*;
 use crate::common::*;
+use crate::sim::{Board, Direction, Pos};
 
 //=============================================================================
 //    GameScene
@@ -9,40 +10,36 @@ pub struct GameScene {
     height: f32,
     grid_size: f32,
     grid_alpha: f32,
-    lines: Vec<Line>,
-    gates_opened: bool,
-    timer: Timer,
+    open_borders: bool,  // The board wraps, the grid covers the outer wall too
+    lines: Vec<Line>
 }
 
 impl GameScene {
-    const GRID_FADE_SPEED: f32 = 0.03;
+    const LINE_ALPHA_DIFF: f32 = 0.05;
 
     pub fn new(width: f32, height: f32, grid_size: f32) 
             -> Self {
         let grid_alpha = 0.7;
         let lines = Vec::new(); 
-        let gates_opened = true;
-        let timer = Timer::new(1.0 / 60.0); 
         let mut new_scene = Self { 
-            width, height, grid_size, grid_alpha, 
-            lines, gates_opened, timer
+            width, height, grid_size, grid_alpha, open_borders: false, lines
         };
         new_scene.reset();
         new_scene
     }
 
     pub fn reset(&mut self) {
-        self.gates_opened = true;
         self.lines.clear();
 
         let mut alpha: f32 = 0.0;
-        let alpha_diff = 0.05;
+        let alpha_diff = GameScene::LINE_ALPHA_DIFF;
+        let (row_count, column_count) = self.line_counts();
 
-        for y in 0..self.height as u32 {
+        for y in 0..row_count as u32 {
             let line = Line::new( 
                 0.0, 
                 y as f32 * self.grid_size,
-                (self.width - 1.0) * self.grid_size,
+                (column_count - 1) as f32 * self.grid_size,
                 y as f32 * self.grid_size,
                 alpha);
             self.lines.push(line);
@@ -50,12 +47,12 @@ impl GameScene {
         }
 
         alpha = 0.0;
-        for x in 0..self.width as u32 {
+        for x in 0..column_count as u32 {
             let line = Line::new(
                 x as f32 * self.grid_size,
                 0.0, 
                 x as f32 * self.grid_size,
-                (self.height - 1.0) * self.grid_size,
+                (row_count - 1) as f32 * self.grid_size,
                 alpha);
             self.lines.push(line);
             alpha -= alpha_diff; 
@@ -63,22 +60,6 @@ impl GameScene {
 
     }
 
-    pub fn left_gate_position(&self) -> Vec2 {
-        Vec2::new(3.0, self.height - 1.0)
-    }
-
-    pub fn right_gate_position(&self) -> Vec2 {
-        Vec2::new(self.width - 1.0 - 3.0, self.height - 1.0)
-    }
-
-    pub fn open_gates(&mut self) {
-        self.gates_opened = true;
-    }
-
-    pub fn close_gates(&mut self) {
-        self.gates_opened = false;
-    }
-
     pub fn width(&self) -> f32 {
         self.width
     }
@@ -91,35 +72,41 @@ impl GameScene {
         self.grid_size
     }
 
-    pub fn play_area(&self) -> Rect {
-        Rect::new(1.0, 1.0, self.width - 2.0, self.height - 2.0)
+    pub fn open_borders(&self) -> bool {
+        self.open_borders
     }
 
-    pub fn animate_grid(&mut self) -> bool {
-        let mut finished_lines = 0;
+    pub fn set_open_borders(&mut self, open_borders: bool) {
+        self.open_borders = open_borders;
+        self.reset();
+    }
 
-        if self.timer.update() {
-            for line in &mut self.lines {
-                line.alpha += GameScene::GRID_FADE_SPEED;
-                if line.alpha > self.grid_alpha {
-                    line.alpha = self.grid_alpha; 
-                    finished_lines += 1;
-                }
-            }
-            self.timer.reset();
-        }
+    // Fades the grid in line by line; progress runs from 0.0 to 1.0 while
+    // the players get ready
+    pub fn animate_grid(&mut self, progress: f32) {
+        let (row_count, column_count) = self.line_counts();
+        let line_count = row_count.max(column_count) as f32;
+        let fade_range = self.grid_alpha + GameScene::LINE_ALPHA_DIFF * (line_count - 1.0);
 
-        finished_lines != self.lines.len() 
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            let line_index = if i < row_count { i } else { i - row_count };
+            let alpha = fade_range * progress.min(1.0) 
+                      - GameScene::LINE_ALPHA_DIFF * line_index as f32;
+            line.alpha = alpha.min(self.grid_alpha);
+        }
     }
 
-    pub fn draw(&self, texture: &Texture2D) {
+    pub fn draw(&self, texture: &Texture2D, board: &Board) {
         self.draw_grid(Color::new(0.663, 0.373, 0.263, 1.0));
-        self.draw_texture_borders(texture);
+        self.draw_open_borders(Color::new(0.9, 0.6, 0.4, 1.0));
+        let color = Color::new(1.0, 1.0, 1.0, 1.0);
+        self.draw_walls(board, |pos| self.draw_texture(texture, pos, &color));
     }
 
-    pub fn draw_basic(&self) {
+    pub fn draw_basic(&self, board: &Board) {
         self.draw_grid(Color::new(0.3, 0.3, 1.0, 1.0));
-        self.draw_borders();
+        self.draw_open_borders(Color::new(0.5, 0.5, 1.0, 1.0));
+        self.draw_walls(board, |pos| self.draw_block(pos, &BLUE));
     }
 
     pub fn draw_block(&self, pos: &Vec2, color: &Color) {
@@ -172,39 +159,56 @@ impl GameScene {
         }
     }
 
-    fn draw_borders(&self) {
-        let color = BLUE;
-
-        for x in 0..self.width as u32{
-            self.draw_block(&Vec2::new(x as f32, 0.0), &color);
-            if !self.gates_opened || 
-                    ( (x as f32 - self.left_gate_position().x).abs() > 1.0
-                    && (x as f32 - self.right_gate_position().x).abs() > 1.0 ) {
-                self.draw_block(&Vec2::new(x as f32 , 
-                                self.height as f32 - 1.0), &color);
-            }
+    // Dashes around the edge of the board, where the snakes go across
+    fn draw_open_borders(&self, color: Color) {
+        if !self.open_borders {
+            return;
         }
 
+        let (right, bottom) = (self.width * self.grid_size, self.height * self.grid_size);
+        let dash = self.grid_size / 2.0;
+        for x in 0..self.width as u32 {
+            let left = x as f32 * self.grid_size + dash / 2.0;
+            draw_line(left, 1.0, left + dash, 1.0, 2.0, color);
+            draw_line(left, bottom - 1.0, left + dash, bottom - 1.0, 2.0, color);
+        }
         for y in 0..self.height as u32 {
-            self.draw_block(&Vec2::new(0.0, y as f32), &color);
-            self.draw_block(&Vec2::new(self.width as f32 - 1.0, y as f32), 
-                            &color);
+            let top = y as f32 * self.grid_size + dash / 2.0;
+            draw_line(1.0, top, 1.0, top + dash, 2.0, color);
+            draw_line(right - 1.0, top, right - 1.0, top + dash, 2.0, color);
         }
     }
 
-    fn draw_texture_borders(&self, texture: &Texture2D) {
-        let color = Color::new(1.0, 1.0, 1.0, 1.0);
-
-        for x in 0..self.width as u32{
-            self.draw_texture(texture, &Vec2::new(x as f32, 0.0), &color);
-            self.draw_texture(texture, &Vec2::new(x as f32, 
-                            self.height as f32 - 1.0), &color);
+    fn draw_walls<F: Fn(&Vec2)>(&self, board: &Board, draw_wall: F) {
+        for y in 0..board.height() {
+            for x in 0..board.width() {
+                let pos = Pos::new(x, y);
+                if board.is_wall(&pos) && !self.is_open_gate(board, &pos) {
+                    draw_wall(&pos_to_vec2(pos));
+                }
+            }
         }
+    }
 
-        for y in 0..self.height as u32 {
-            self.draw_texture(texture, &Vec2::new(0.0, y as f32), &color);
-            self.draw_texture(texture, &Vec2::new(
-                            self.width as f32 - 1.0, y as f32), &color);
+    // An open gate leaves a gap of three cells in the outer wall
+    fn is_open_gate(&self, board: &Board, pos: &Pos) -> bool {
+        board.gates_opened() && board.gates().any(|gate| {
+            match gate.dir {
+                Direction::Up | Direction::Down => 
+                    pos.y == gate.pos.y && (pos.x - gate.pos.x).abs() <= 1,
+                Direction::Left | Direction::Right => 
+                    pos.x == gate.pos.x && (pos.y - gate.pos.y).abs() <= 1
+            }
+        })
+    }
+
+    // Horizontal and vertical grid lines. The outer wall has none, unless the
+    // borders are open.
+    fn line_counts(&self) -> (usize, usize) {
+        if self.open_borders {
+            (self.height as usize + 1, self.width as usize + 1)
+        } else {
+            (self.height as usize, self.width as usize)
         }
     }
 
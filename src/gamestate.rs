@@ -1,228 +1,636 @@
 use macroquad::prelude::*;
+use datapak::PakResult;
+use crate::arena::Arena;
+use crate::campaign::*;
+use crate::editor::*;
 use crate::assets::*;
 use crate::gamescene::*;
 use crate::snake::*;
 use crate::apple::*;
 use crate::label::*;
-use crate::common::*;
+use crate::playback::*;
+use crate::keyboard::*;
+use crate::seed::*;
+use crate::sim::*;
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 //=============================================================================
 //    GameState
 //=============================================================================
-#[derive(Eq, PartialEq)]
-pub enum LevelState {
-    GetReady, SnakeEntering, Playing, Stunned, Dying, GameOver
-}
-
+// Feeds keyboard input into the sim::World and draws the result. The world
+// runs in fixed ticks; frame time only decides how many ticks are due. Every
+// game is recorded, and a replay can take the place of the keyboard. In the
+// campaign the boards and rules change from level to level. The editor
+// takes over input and drawing while a map is edited.
+// Drawing is in the pixels of the arena's window size, whatever the window's.
 pub struct GameState {
     assets: Assets,
+    arena: Arena,
     game_scene: GameScene,
-    player_count: usize,
-    players: [Snake; 2],
-    apples: Vec<Apple>,
-    scores: [i32; 2],
-    game_time: f32,
-    spawn_timer: Timer,
-    delay_timer: Timer,
+    boards: Vec<Board>,  // The built-in arena, then the maps
+    board_index: usize,
+    random_arena: bool,  // A new generated board for every free play game
+    wrap: bool,  // Free play boards wrap around at the edges
+    campaign: Option<Campaign>,  // While the campaign is played
+    editor: Option<Editor>,  // Kept while the edited map is test-played
+    editing: bool,
+    world: World,
+    seed_mode: SeedMode,
+    seed: u64,
+    tick_accumulator: f32,
+    recording: Replay,
+    playback: Option<Playback>,
+    controllers: Vec<Box<dyn Controller>>,
+    difficulty: Difficulty,
+    versus_computer: bool,
+    bots: Vec<bool>,
+    sprites: Vec<SnakeSprite>,
     label_announce: Label,
-    labels_length_title: [Label; 2],
-    labels_length: [Label; 2],
-    labels_score_title: [Label; 2],
-    labels_score: [Label; 2],
+    labels_length_title: Vec<Label>,
+    labels_length: Vec<Label>,
+    labels_score_title: Vec<Label>,
+    labels_score: Vec<Label>,
+    label_seed: Label,
+    label_mode: Label,
+    label_goal: Label,
     basic_actor: bool,
-    basic_scene: bool,
-    pub substate: LevelState,
+    basic_scene: bool
 }
 
 impl GameState {
-    pub fn new(width: f32, height: f32, grid_size: f32) -> GameState {
-        const SPAWN_TIME: f32 = 2.0;
-        let game_scene = GameScene::new(width, height, grid_size);
-        let left_gate = game_scene.left_gate_position();
-        let right_gate = game_scene.right_gate_position();
+    const REPLAY_DIR: &'static str = "replays";
+    const NEXT_LEVEL_TICKS: u32 = 2 * TICK_RATE;
+    const RANDOM_ARENA: &'static str = "random";
+
+    pub fn new(arena: Arena, seed_mode: SeedMode, difficulty: Difficulty) -> GameState {
+        let seed = seed_mode.next_seed();
+        println!("{}", seed_mode.label(seed));
+        let world = World::new(arena.width, arena.height, seed);
+        let recording = Replay::new(seed, &world);
 
         GameState { 
             assets: Assets::new(),
-            game_scene: game_scene, 
-            player_count: 1,
-            players: { 
-                let bound = Rect::new(1.0, 1.0, width - 2.0, height - 2.0);
-                [
-                    Snake::new(0, GREEN, Vec2::new(right_gate.x, right_gate.y + 1.0), bound),
-                    Snake::new(1, PINK, Vec2::new( left_gate.x, left_gate.y + 1.0), bound)
-                ]
-            },
-            apples: Vec::new(),
-            scores: [0, 0],
-            game_time: 0.0,
-            spawn_timer: Timer::new(SPAWN_TIME), 
-            delay_timer: Timer::new(Snake::STUN_INTERVAL), 
+            arena,
+            game_scene: GameScene::new(arena.width as f32, arena.height as f32, arena.grid_size), 
+            boards: vec![Board::new(arena.width, arena.height)],
+            board_index: 0,
+            random_arena: false,
+            wrap: false,
+            campaign: None,
+            editor: None,
+            editing: false,
+            world,
+            seed_mode,
+            seed,
+            tick_accumulator: 0.0,
+            recording,
+            playback: None,
+            controllers: (0..World::MAX_PLAYERS)
+                .map(|i| Box::new(KeyboardController::for_player(i)) as Box<dyn Controller>)
+                .collect(),
+            difficulty,
+            versus_computer: false,
+            bots: vec![false; World::MAX_PLAYERS],
+            sprites: vec![
+                SnakeSprite::new(GREEN, Assets::TEX_SNAKE1, WHITE), 
+                SnakeSprite::new(PINK, Assets::TEX_SNAKE2, WHITE),
+                SnakeSprite::new(ORANGE, Assets::TEX_SNAKE2, Color::new(1.0, 0.8, 0.2, 1.0)),
+                SnakeSprite::new(SKYBLUE, Assets::TEX_SNAKE1, Color::new(0.3, 0.8, 1.0, 1.0))
+            ],
             label_announce: Label::new(),
-            labels_length_title: { [Label::new(), Label::new()] },
-            labels_length: { [Label::new(), Label::new()] },
-            labels_score_title: { [Label::new(), Label::new()] },
-            labels_score: { [Label::new(), Label::new()] },
+            labels_length_title: (0..World::MAX_PLAYERS).map(|_| Label::new()).collect(),
+            labels_length: (0..World::MAX_PLAYERS).map(|_| Label::new()).collect(),
+            labels_score_title: (0..World::MAX_PLAYERS).map(|_| Label::new()).collect(),
+            labels_score: (0..World::MAX_PLAYERS).map(|_| Label::new()).collect(),
+            label_seed: Label::new(),
+            label_mode: Label::new(),
+            label_goal: Label::new(),
             basic_actor: false, 
-            basic_scene: true, 
-            substate: LevelState::GetReady
+            basic_scene: true
         }
     }
 
-    pub async fn load(&mut self) {
-        self.assets.load_from_datapak().await;
+    pub async fn load(&mut self, source: AssetSource) -> PakResult<()> {
+        self.assets.load(source).await?;
+        self.boards.extend(self.assets.maps().iter().cloned());
+        for level in self.assets.campaign() {
+            if self.find_board(&level.map).is_none() {
+                println!("Campaign map {} is not installed", level.map);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn arena(&self) -> Arena {
+        self.arena
+    }
+
+    // "random" plays random arenas. Returns false if there is no map of that name.
+    pub fn select_map(&mut self, name: &str) -> bool {
+        let player_count = self.world.player_count();
+        if name.eq_ignore_ascii_case(GameState::RANDOM_ARENA) {
+            self.random_arena = true;
+            self.reset(player_count);
+            return true;
+        }
+
+        match self.boards.iter().position(|board| board.name().eq_ignore_ascii_case(name)) {
+            Some(index) => {
+                self.random_arena = false;
+                self.board_index = index;
+                self.set_board(self.boards[index].clone());
+                self.reset(player_count);
+                true
+            },
+            None => false
+        }
+    }
+
+    // Starts a new free play game on a board that wraps around or not. The
+    // campaign keeps its walls.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        if self.campaign.is_none() && self.playback.is_none() {
+            self.set_board_wrap(wrap);
+            self.reset(self.world.player_count());
+        }
+    }
+
+    // Starts at the level the last session got to
+    pub fn start_campaign(&mut self) {
+        let levels: Vec<CampaignLevel> = self.assets.campaign().iter()
+            .filter(|level| self.find_board(&level.map).is_some())
+            .cloned()
+            .collect();
+        if levels.is_empty() {
+            println!("There is no campaign to play");
+            return;
+        }
+
+        self.set_versus_computer(false);
+        self.campaign = Some(Campaign::new(levels));
+        self.start_level();
+    }
+
+    pub async fn reload_assets(&mut self) {
+        self.assets.reload_changed().await;
     }
 
     pub fn start(&self) {
         self.assets.play_sound(Assets::SND_GET_READY);
     }
 
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self, player_count: usize) {
+        self.seed = self.seed_mode.next_seed();
+        println!("{}", self.seed_mode.label(self.seed));
+        // The random arena comes from the game's seed, like the apples
+        if self.random_arena && self.campaign.is_none() && self.editor.is_none() {
+            let arena = &self.boards[0];
+            self.set_board(Board::random_arena(arena.width(), arena.height(), self.seed));
+        }
         self.game_scene.reset();
+        self.world.reset(player_count, self.seed);
+        self.recording = Replay::new(self.seed, &self.world);
+        self.assets.play_sound(Assets::SND_GET_READY);
+    }
 
-        for i in 0..self.player_count {
-            self.players[i].reset();
+    // Replaces the keyboard with the replay until the game is quit. The replay
+    // is played on its own board.
+    pub fn start_playback(&mut self, replay: Replay) -> io::Result<()> {
+        let board = if replay.map.is_empty() {
+            Board::new(replay.width, replay.height)
+        } else {
+            let generated = Board::from_arena_name(&replay.map, replay.width, replay.height);
+            match self.find_board(&replay.map).cloned().or(generated) {
+                Some(board) => board,
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, 
+                    format!("replay map {} is not installed", replay.map)))
+            }
+        };
+        if replay.width != board.width() || replay.height != board.height() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, 
+                format!("replay board is {}x{}, map {} is {}x{}", replay.width, replay.height, 
+                        replay.map, board.width(), board.height())));
         }
 
-        self.apples.clear();
-        self.scores[0] = 0;
-        self.scores[1] = 0;
-        self.game_time = 0.0;
-        self.spawn_timer.reset(); 
-        self.delay_timer.reset(); 
-        self.substate = LevelState::GetReady;
-        self.assets.play_sound(Assets::SND_GET_READY);
+        self.set_board(board);
+        self.playback = Some(Playback::new(replay));
+        self.restart_playback();
+        Ok(())
+    }
+
+    // A bot keeps its player for the whole session, whatever game is selected
+    pub fn attach_bot(&mut self, id: usize, bot: Box<dyn Controller>) {
+        self.controllers[id] = bot;
+        self.bots[id] = true;
+        if id == 1 {
+            self.versus_computer = false;
+        }
     }
 
+    // F1 one player, F2 two players, F3 one player against the computer,
+    // F4 changes the computer's difficulty, F7 three players, F8 four,
+    // F9 moves on to the next map or random arenas, F12 plays the campaign, Tab edits the map
+    // (or goes back to editing it after a test game)
     pub fn handle_input(&mut self) {
-        match self.substate {
-            LevelState::Playing => {
-                let mut dir_changed = 
-                    if is_key_down(KeyCode::Up) {
-                        self.players[0].set_direction(Direction::Up)
-                    } else if is_key_down(KeyCode::Down) {
-                        self.players[0].set_direction(Direction::Down)
-                    } else if is_key_down(KeyCode::Left) {
-                        self.players[0].set_direction(Direction::Left)
-                    } else if is_key_down(KeyCode::Right) {
-                        self.players[0].set_direction(Direction::Right)
-                    } else {
-                        false
-                    };
-
-                dir_changed = dir_changed || 
-                    if is_key_down(KeyCode::W) {
-                        self.players[1].set_direction(Direction::Up)
-                    } else if is_key_down(KeyCode::S) {
-                        self.players[1].set_direction(Direction::Down)
-                    } else if is_key_down(KeyCode::A) {
-                        self.players[1].set_direction(Direction::Left)
-                    } else if is_key_down(KeyCode::D) {
-                        self.players[1].set_direction(Direction::Right)
-                    } else {
-                        false
-                    };
-
-                if dir_changed {
-                    self.assets.play_sound(Assets::SND_MOVE);
-                }
+        let ended = match self.world.state() {
+            LevelState::GameOver => true,
+            LevelState::LevelComplete => match &self.campaign {
+                Some(campaign) => campaign.is_finished(),
+                None => true
             },
-            LevelState::GameOver => {
-                if is_key_down(KeyCode::Enter) || is_key_down(KeyCode::Space) {
-                    self.reset();
-                }
-            },
-            _ => { }
+            _ => false
+        };
+        if ended && (is_key_down(KeyCode::Enter) || is_key_down(KeyCode::Space)) {
+            if self.playback.is_some() {
+                self.restart_playback();
+                self.start();
+            } else if let Some(campaign) = self.campaign.as_mut().filter(|c| c.is_finished()) {
+                campaign.restart();
+                self.start_level();
+            } else {
+                self.reset(self.world.player_count());
+            }
         }
 
-        if is_key_pressed(KeyCode::F1) {
-            self.player_count = 1;
-            self.reset();
+        if self.playback.is_some() {
+            // Player count comes from the replay
+        } else if is_key_pressed(KeyCode::F1) {
+            self.leave_campaign();
+            self.set_versus_computer(false);
+            self.reset(1);
         } else if is_key_pressed(KeyCode::F2) {
-            self.player_count = 2;
-            self.reset();
-        } else if is_key_pressed(KeyCode::F5) {
+            self.leave_campaign();
+            self.set_versus_computer(false);
+            self.reset(2);
+        } else if is_key_pressed(KeyCode::F3) {
+            self.leave_campaign();
+            self.set_versus_computer(true);
+            self.reset(2);
+        } else if is_key_pressed(KeyCode::F7) {
+            self.leave_campaign();
+            self.set_versus_computer(false);
+            self.reset(3);
+        } else if is_key_pressed(KeyCode::F8) {
+            self.leave_campaign();
+            self.set_versus_computer(false);
+            self.reset(4);
+        } else if is_key_pressed(KeyCode::F9) {
+            // The maps, then random arenas, then the maps again
+            self.campaign = None;
+            let player_count = self.world.player_count();
+            if self.random_arena {
+                self.random_arena = false;
+                self.board_index = 0;
+            } else if self.board_index + 1 < self.boards.len() {
+                self.board_index += 1;
+            } else {
+                self.random_arena = true;
+            }
+            self.set_free_play_board();
+            self.reset(player_count);
+        } else if is_key_pressed(KeyCode::F12) {
+            self.start_campaign();
+        } else if is_key_pressed(KeyCode::Tab) {
+            self.open_editor();
+        } else if is_key_pressed(KeyCode::F4) {
+            self.difficulty = self.difficulty.next();
+            if self.versus_computer {
+                self.set_versus_computer(true);
+                self.reset(2);
+            }
+        }
+
+        if is_key_pressed(KeyCode::F5) {
             self.basic_actor = !self.basic_actor;
         } else if is_key_pressed(KeyCode::F6) {
             self.basic_scene = !self.basic_scene;
         }
-
     }
 
-    pub fn update(&mut self) {
-        self.handle_input();
+    // mouse is in the pixels of the arena's window size
+    pub fn update(&mut self, mouse: Vec2) {
+        // After a long stall, drop the time rather than running a burst of ticks
+        const MAX_FRAME_TIME: f32 = 0.25;
 
-        match &self.substate {
-            LevelState::GetReady => {
-                if !self.game_scene.animate_grid() {
-                    self.substate = LevelState::SnakeEntering;
-                }
-            },
-            LevelState::SnakeEntering => {
-                self.update_actors();
-                let player = &self.players[0];
-                if player.position().y == self.game_scene.height() - 1.0 - player.length() as f32 {
-                    self.substate = LevelState::Playing;
-                    self.game_scene.close_gates();
+        if self.editing {
+            self.update_editor(mouse);
+            return;
+        }
+
+        self.handle_input();
+        let frame_time = get_frame_time().min(MAX_FRAME_TIME);
+
+        let mut turned = false;
+        let mut stepped_inputs = Vec::new();
+        match &mut self.playback {
+            Some(playback) => {
+                let step_count = playback.handle_input().unwrap_or(0);
+                if playback.is_paused() {
+                    for _ in 0..step_count {
+                        stepped_inputs.push(playback.next_inputs());
+                    }
+                } else {
+                    self.tick_accumulator += frame_time * playback.speed() as f32;
                 }
             },
-            LevelState::Playing => {
-                self.game_time += get_frame_time();
-                self.update_actors();
-            },
-            LevelState::Stunned => {
-                if self.delay_timer.update() {
-                    self.substate = LevelState::Dying;
-                    self.delay_timer.reset();
+            None => self.tick_accumulator += frame_time
+        }
+
+        for inputs in stepped_inputs {
+            turned |= self.step_world(&inputs);
+        }
+
+        while self.tick_accumulator >= TICK_TIME {
+            self.tick_accumulator -= TICK_TIME;
+
+            let tick_inputs = match &mut self.playback {
+                Some(playback) => playback.next_inputs(),
+                None => {
+                    let inputs = self.controller_inputs();
+                    self.recording.record(&inputs);
+                    inputs
                 }
-            }
-            LevelState::Dying => {
-                self.dying();
-            }
-            LevelState::GameOver => { }
+            };
+            turned |= self.step_world(&tick_inputs);
+        }
+
+        if turned {
+            self.assets.play_sound(Assets::SND_MOVE);
+        }
+
+        // On to the next level, once the snake has been seen leaving
+        let next_level = match &self.campaign {
+            Some(campaign) => self.playback.is_none() && !campaign.is_finished(),
+            None => false
+        };
+        if next_level && self.world.state() == LevelState::LevelComplete 
+                && self.world.state_ticks() >= GameState::NEXT_LEVEL_TICKS {
+            self.start_level();
         }
     }
 
     pub fn draw(&mut self) {
+        if self.editing {
+            let status_panel = self.status_panel();
+            let font = *self.assets.font(Assets::TTF_RETRO);
+            if let Some(editor) = &mut self.editor {
+                editor.draw(&self.game_scene, &font, &status_panel);
+            }
+            return;
+        }
+
+        // Animations are drawn between ticks, by the part of a tick already elapsed
+        let tick_fraction = self.tick_accumulator / TICK_TIME;
+        if self.world.state() == LevelState::GetReady {
+            let ticks = self.world.state_ticks() as f32 + tick_fraction;
+            self.game_scene.animate_grid(ticks / World::GET_READY_TICKS as f32);
+        } else {
+            self.game_scene.animate_grid(1.0);
+        }
+
         if self.basic_scene {
             clear_background(BLACK);
-            self.game_scene.draw_basic();
+            self.game_scene.draw_basic(self.world.board());
         } else {
             clear_background(Color::new(0.325, 0.133, 0.067, 1.0));
-            self.game_scene.draw(self.assets.texture(Assets::TEX_WALL));
+            self.game_scene.draw(self.assets.texture(Assets::TEX_WALL), self.world.board());
         }
-        self.draw_actors();
+        self.draw_actors(tick_fraction);
         self.draw_texts();
     }
 
-    fn draw_actors(&mut self) {
-        if self.basic_actor {
-            let draw_player_order = if !self.players[0].is_alive() { [1, 0] } else { [0, 1] };
-            for i in draw_player_order {
-                if i < self.player_count {
-                    self.players[i].draw_basic(&self.game_scene);
+//=============================================================================
+//  Private methods (GameState)
+//=============================================================================
+    // The window follows the size of the board
+    fn set_board(&mut self, board: Board) {
+        self.arena = Arena { width: board.width(), height: board.height(), ..self.arena };
+        self.game_scene = GameScene::new(board.width() as f32, board.height() as f32, 
+                                         self.arena.grid_size);
+        self.world = World::with_board(board, self.seed);
+        self.set_board_wrap(self.wrap && self.campaign.is_none());
+    }
+
+    fn set_board_wrap(&mut self, wrap: bool) {
+        self.world.set_wrap(wrap);
+        self.game_scene.set_open_borders(wrap);
+    }
+
+    // The built-in arena for an empty name, map names in any case
+    fn find_board(&self, name: &str) -> Option<&Board> {
+        self.boards.iter().find(|board| board.name().eq_ignore_ascii_case(name))
+    }
+
+    fn start_level(&mut self) {
+        let level = match &self.campaign {
+            Some(campaign) => campaign.level().clone(),
+            None => return
+        };
+        if let Some(board) = self.find_board(&level.map).cloned() {
+            self.set_board(board);
+        }
+        self.world.set_apple_goal(level.goal);
+        self.world.set_start_speed(level.speed);
+        self.reset(1);
+    }
+
+    // Edits the board being played, or the one being tested
+    fn open_editor(&mut self) {
+        if self.editor.is_none() {
+            self.leave_campaign();
+            self.editor = Some(Editor::new(self.world.board()));
+        }
+        // Drop what was typed while playing
+        while get_char_pressed().is_some() { }
+        self.editing = true;
+    }
+
+    fn update_editor(&mut self, mouse: Vec2) {
+        let command = match &mut self.editor {
+            Some(editor) => editor.handle_input(mouse, &self.game_scene),
+            None => EditorCommand::Quit
+        };
+
+        match command {
+            EditorCommand::None => { },
+            EditorCommand::TestPlay(board) => {
+                self.editing = false;
+                let player_count = self.world.player_count();
+                self.set_board(board);
+                self.reset(player_count);
+            },
+            // Saved maps can be picked with F9 right away
+            EditorCommand::Saved(board) => {
+                let name = board.name().to_string();
+                match self.boards.iter().position(|other| other.name().eq_ignore_ascii_case(&name)) {
+                    Some(index) => self.boards[index] = board,
+                    None => self.boards.push(board)
                 }
+            },
+            EditorCommand::Quit => {
+                self.editing = false;
+                self.editor = None;
+                let player_count = self.world.player_count();
+                self.set_free_play_board();
+                self.reset(player_count);
             }
+        }
+    }
 
-            for apple in &mut self.apples {
-                apple.draw_basic(&self.game_scene);
+    // Back to the map picked for free play, with the usual rules
+    fn leave_campaign(&mut self) {
+        if self.campaign.take().is_some() {
+            self.set_free_play_board();
+        }
+    }
+
+    // A random arena is only made on reset
+    fn set_free_play_board(&mut self) {
+        if !self.random_arena {
+            self.set_board(self.boards[self.board_index].clone());
+        }
+    }
+
+    // Player 2 is played by the computer in versus computer games, unless a
+    // bot already plays it
+    fn set_versus_computer(&mut self, versus_computer: bool) {
+        if self.bots[1] {
+            return;
+        }
+        self.versus_computer = versus_computer;
+        self.controllers[1] = if versus_computer {
+            self.difficulty.new_ai()
+        } else {
+            Box::new(KeyboardController::for_player(1))
+        };
+    }
+
+    fn controller_inputs(&mut self) -> Vec<Option<Direction>> {
+        let world = &self.world;
+        self.controllers.iter_mut().enumerate()
+            .map(|(i, controller)| controller.next_direction(world, i))
+            .collect()
+    }
+
+    // Returns true if a snake turned
+    fn step_world(&mut self, inputs: &[Option<Direction>]) -> bool {
+        let mut turned = false;
+
+        for event in self.world.step(inputs) {
+            match event {
+                Event::Turned(_) => turned = true,
+                Event::AteApple(i) => {
+                    println!("Speed: {}", self.world.snake(i).speed());
+                    self.assets.play_sound(Assets::SND_EAT);
+                },
+                Event::Crashed(i, crash) => {
+                    match crash {
+                        Crash::Wall => println!("Player {} crashed into the wall!", i + 1),
+                        Crash::Itself => println!("Player {} crashed into yourself!", i + 1),
+                        Crash::Opponent => println!("Player {} crashed into the opponent!", i + 1)
+                    }
+                    self.assets.play_sound(Assets::SND_DEAD);
+                },
+                Event::GatesOpened => {
+                    println!("The gates are open!");
+                    self.assets.play_sound(Assets::SND_GET_READY);
+                },
+                Event::LevelComplete => {
+                    if self.playback.is_none() {
+                        self.save_recording();
+                    }
+                    if let Some(campaign) = &mut self.campaign {
+                        campaign.complete_level(self.world.score(0));
+                    }
+                },
+                Event::GameOver => {
+                    if self.playback.is_none() {
+                        self.save_recording();
+                    }
+                },
+                Event::GatesClosed => { }
+            }
+        }
+
+        turned
+    }
+
+    fn restart_playback(&mut self) {
+        if let Some(playback) = &mut self.playback {
+            let replay = playback.replay();
+            let (player_count, seed) = (replay.player_count, replay.seed);
+            self.world.set_apple_goal(replay.apple_goal);
+            self.world.set_start_speed(replay.start_speed);
+            self.world.set_wrap(replay.wrap);
+            self.game_scene.set_open_borders(replay.wrap);
+            playback.rewind();
+            self.seed = seed;
+            self.world.reset(player_count, seed);
+            self.tick_accumulator = 0.0;
+        }
+    }
+
+    // Saved as replays/<unix time>.rpl, a failure only costs the replay
+    fn save_recording(&self) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        let file_name = format!("{}/{}.rpl", GameState::REPLAY_DIR, time);
+
+        let result = fs::create_dir_all(GameState::REPLAY_DIR)
+            .and_then(|_| self.recording.save(&file_name));
+        match result {
+            Ok(()) => println!("Replay saved to {}", file_name),
+            Err(e) => println!("Error saving replay {}: {}", file_name, e)
+        }
+    }
+
+    // Right of the board, down to the bottom of the window
+    fn status_panel(&self) -> Rect {
+        let playfield_width = self.game_scene.width() * self.game_scene.grid_size();
+        Rect::new(playfield_width, 0.0, 
+                  self.arena.window_width() - playfield_width, self.arena.window_height())
+    }
+
+    fn draw_actors(&mut self, tick_fraction: f32) {
+        // Crashed snakes on top, to show what they ran into
+        let mut draw_player_order: Vec<usize> = (0..self.world.player_count()).collect();
+        draw_player_order.sort_by_key(|&i| !self.world.snake(i).is_alive());
+
+        // Snakes glide from cell to cell while they are moving
+        let moving = matches!(self.world.state(), LevelState::SnakeEntering 
+                                                  | LevelState::Playing | LevelState::Exiting);
+        let steps: Vec<f32> = (0..self.world.player_count())
+            .map(|i| {
+                let snake = self.world.snake(i);
+                if moving && snake.is_alive() { snake.step_fraction(tick_fraction) } else { 1.0 }
+            })
+            .collect();
+
+        if self.basic_actor {
+            for &i in &draw_player_order {
+                self.sprites[i].draw_basic(self.world.snake(i), steps[i], &self.game_scene);
+            }
+
+            for apple in self.world.apples() {
+                draw_apple_basic(apple, tick_fraction, &self.game_scene);
             }
         } else {
-            let draw_player_order = if !self.players[0].is_alive() { [1, 0] } else { [0, 1] };
-            
-            for i in draw_player_order {
-                if i < self.player_count {
-                    self.players[i].draw(
-                        &self.assets.texture( 
-                            if self.players[i].id() == 0 { Assets::TEX_SNAKE1 } 
-                            else { Assets::TEX_SNAKE2 }
-                        ),
-                        if self.substate == LevelState::GetReady { false } else { true },
-                        &self.game_scene);
-                }
+            for &i in &draw_player_order {
+                self.sprites[i].draw(
+                    self.world.snake(i),
+                    steps[i],
+                    self.assets.texture(self.sprites[i].texture),
+                    self.world.state() != LevelState::GetReady,
+                    &self.game_scene);
             }
 
-            for apple in &mut self.apples {
-                apple.draw(&self.assets.texture(Assets::TEX_APPLE), &self.game_scene);
+            for apple in self.world.apples() {
+                draw_apple(apple, tick_fraction, self.assets.texture(Assets::TEX_APPLE), 
+                           &self.game_scene);
             }
         }
     }
@@ -238,54 +646,113 @@ impl GameState {
             else { Assets::TTF_ELEGANT } 
         );
 
-        // Draw status text
+        // Draw status text, with score and length side by side when more
+        // than two players have to fit in the panel
         {
             const LINE_SPACING: f32 = 16.0;
             const PLAYER_SPACING: f32 = 100.0;
+            const COMPACT_LINE_SPACING: f32 = 8.0;
+            const COMPACT_PLAYER_SPACING: f32 = 40.0;
 
+            let compact = self.world.player_count() > 2;
             let title_font_size: u16 = if self.basic_scene { 30 } else { 34 };
-            let font_size: u16 = if self.basic_scene { 42 } else { 44 };
-            let status_panel: Rect = Rect::new(
-                playfield.w, 0.0, screen_width() - playfield.w, screen_height() 
-             );
+            let font_size: u16 = match (compact, self.basic_scene) {
+                (false, true) => 42,
+                (false, false) => 44,
+                (true, true) => 26,
+                (true, false) => 28
+            };
+            let player_title_font_size: u16 = match (compact, self.basic_scene) {
+                (false, _) => title_font_size,
+                (true, true) => 22,
+                (true, false) => 24
+            };
+            let line_spacing = if compact { COMPACT_LINE_SPACING } else { LINE_SPACING };
+            let player_spacing = if compact { COMPACT_PLAYER_SPACING } else { PLAYER_SPACING };
+
+            let status_panel: Rect = self.status_panel();
+            let (score_column, length_column) = if compact {
+                let half_width = status_panel.w / 2.0;
+                (Rect::new(status_panel.x, 0.0, half_width, status_panel.h),
+                 Rect::new(status_panel.x + half_width, 0.0, half_width, status_panel.h))
+            } else {
+                (status_panel, status_panel)
+            };
             let mut top: f32 = 50.0;
 
-            for i in 0..self.player_count {
-                let color: &Color = &self.players[i].color;
+            for i in 0..self.world.player_count() {
+                let color: &Color = &self.sprites[i].color;
                 self.labels_score_title[i]
                     .set_color(color)
-                    .set_font(&font, title_font_size)
+                    .set_font(&font, player_title_font_size)
                     .set_text("Score")
-                    .center(None, Some(top), &status_panel)
+                    .center(None, Some(top), &score_column)
                     .draw(); 
 
                 self.labels_score[i]
                     .set_color(color)
                     .set_font(&font, font_size)
-                    .set_text(&format!("{:07}", self.scores[i]).as_str())
-                    .center(None, Some(self.labels_score_title[i].bottom() + LINE_SPACING), 
-                            &status_panel)
+                    .set_text(&format!("{:07}", self.world.score(i)).as_str())
+                    .center(None, Some(self.labels_score_title[i].bottom() + line_spacing), 
+                            &score_column)
                     .draw(); 
 
+                let length_top = if compact { 
+                    top 
+                } else { 
+                    self.labels_score[i].bottom() + 2.0 * line_spacing 
+                };
                 self.labels_length_title[i]
                     .set_color(color)
-                    .set_font(&font, title_font_size)
+                    .set_font(&font, player_title_font_size)
                     .set_text("Length")
-                    .center(None, Some(self.labels_score[i].bottom() + 2.0 * LINE_SPACING), 
-                            &status_panel)
+                    .center(None, Some(length_top), &length_column)
                     .draw(); 
 
                 self.labels_length[i]
                     .set_color(color)
                     .set_font(&font, font_size)
-                    .set_text(&format!("{}", self.players[i].length()).as_str())
-                    .center(None, Some(self.labels_length_title[i].bottom() + LINE_SPACING), 
-                            &status_panel)
+                    .set_text(&format!("{}", self.world.snake(i).length()).as_str())
+                    .center(None, Some(self.labels_length_title[i].bottom() + line_spacing), 
+                            &length_column)
                     .draw(); 
 
-                if i == 0 {
-                    top = self.labels_length[i].bottom() + PLAYER_SPACING;
-                }
+                top = self.labels_length[i].bottom() + player_spacing;
+            }
+
+            // Apples still to eat before the gates open
+            if self.world.apple_goal() > 0 {
+                let goal_text = if self.world.apples_eaten() >= self.world.apple_goal() {
+                    String::from("Gates open")
+                } else {
+                    format!("Apples {}/{}", self.world.apples_eaten(), self.world.apple_goal())
+                };
+                self.label_goal
+                    .set_color(&WHITE)
+                    .set_font(&font, player_title_font_size)
+                    .set_text(&goal_text)
+                    .center(None, Some(top), &status_panel)
+                    .draw();
+            }
+
+            let mode_text = match (&self.playback, &self.campaign) {
+                (Some(playback), _) if playback.is_paused() => Some(String::from("Paused")),
+                (Some(playback), _) if playback.is_finished() => Some(String::from("Replay end")),
+                (Some(playback), _) => Some(format!("Replay {}x", playback.speed())),
+                (None, Some(campaign)) => {
+                    Some(format!("Level {}/{}", campaign.level_number(), campaign.level_count()))
+                },
+                (None, None) if self.versus_computer => Some(format!("CPU {}", self.difficulty.name())),
+                (None, None) if self.bots.contains(&true) => Some(String::from("Bot game")),
+                (None, None) => None
+            };
+            if let Some(text) = mode_text {
+                self.label_mode
+                    .set_color(&WHITE)
+                    .set_font(&font, title_font_size)
+                    .set_text(&text)
+                    .center(None, Some(status_panel.bottom() - 60.0), &status_panel)
+                    .draw();
             }
         }
 
@@ -297,86 +764,64 @@ impl GameState {
             .set_color(&WHITE)
             .set_shadow(&Vec2::new(2.0, 2.0), &Color::new(1.0, 0.0, 0.3, 1.0));
 
-        // Draw get ready text
-        if self.substate == LevelState::GetReady {
+        // Draw get ready text, with the name of the map or the level
+        if self.world.state() == LevelState::GetReady {
             self.label_announce
                 .set_text("Get Ready")
                 .center(None, None, &playfield)
                 .draw();
+
+            let map_name = self.world.board().name();
+            let map_text = match &self.campaign {
+                Some(campaign) if map_name.is_empty() => format!("Level {}", campaign.level_number()),
+                Some(campaign) => format!("Level {} - {}", campaign.level_number(), map_name),
+                None => map_name.to_string()
+            };
+            if !map_text.is_empty() {
+                let map_font_size: u16 = if self.basic_scene { 30 } else { 34 };
+                self.label_seed
+                    .set_font(&font, map_font_size)
+                    .set_color(&WHITE)
+                    .set_text(&map_text)
+                    .center(None, Some(self.label_announce.bottom() + 20.0), &playfield)
+                    .draw();
+            }
         } 
 
-        // Draw game over text
-        if self.substate == LevelState::GameOver {
+        // Draw level complete text, with the campaign score so far
+        if self.world.state() == LevelState::LevelComplete {
+            let finished = self.campaign.as_ref().is_some_and(|campaign| campaign.is_finished());
             self.label_announce
-                .set_text("GameOver")
+                .set_text(if finished { "Campaign Complete" } else { "Level Complete" })
                 .center(None, None, &playfield)
                 .draw();
-        }
-        
-    }
-
-    pub fn player_by_id(&self, id: i32) -> Option<&Snake> {
-        if id < 0 || id >= self.player_count as i32 {
-            None
-        } else {
-            Some(&self.players[id as usize]) }
-    }
-
-    fn update_scores(&mut self) {
-        for i in 0..self.player_count {
-            self.scores[i] +=  (self.players[i].length() as f32 * 0.2) as i32;
-        }
-    }
-
-    pub fn update_actors(&mut self) {
-        const MAX_APPLES: usize = 3;
-
-        if self.spawn_timer.update() && self.apples.len() < MAX_APPLES {
-            let apple = Apple::random_spawn(&self.game_scene.play_area(), 
-                                            &self.players[0..self.player_count]);
-            self.apples.push(apple);
-            self.spawn_timer.reset();
-        }
-
-        for apple in &mut self.apples {
-            apple.update();
-        }
 
-        for i  in 0..self.player_count {
-            if self.players[i].check_update_time() {
-                let opponent_id: i32 = self.opponent_player_index(i as i32);
-                if self.substate == LevelState::Playing 
-                        && self.players[i].check_collision(&self, opponent_id) {
-                    self.players[i].update();
-                    self.players[i].kill_self();
-                    self.assets.play_sound(Assets::SND_DEAD);
-                    self.substate = LevelState::Stunned;
-                } else {
-                    self.players[i].update();
-                    if self.players[i].eat_apples(&mut self.apples) {
-                        self.assets.play_sound(Assets::SND_EAT);
-                    }
-                }
-                self.update_scores();
+            if let Some(campaign) = &self.campaign {
+                let score_font_size: u16 = if self.basic_scene { 30 } else { 34 };
+                self.label_seed
+                    .set_font(&font, score_font_size)
+                    .set_color(&WHITE)
+                    .set_text(&format!("Total score {}", campaign.score()))
+                    .center(None, Some(self.label_announce.bottom() + 20.0), &playfield)
+                    .draw();
             }
         }
-    }
 
-    fn opponent_player_index(&self, id: i32) -> i32 { 
-        if self.player_count == 1 {
-            -1
-        } else if id == 0 { 
-            1
-        } else { 
-            0
-        }
-    }
+        // Draw game over text, with the seed to share or play again
+        if self.world.state() == LevelState::GameOver {
+            self.label_announce
+                .set_text("GameOver")
+                .center(None, None, &playfield)
+                .draw();
 
-    fn dying(&mut self) {
-        for player in &mut self.players {
-            if !player.is_alive() && !player.dying() {
-                self.substate = LevelState::GameOver;
-            }
+            let seed_font_size: u16 = if self.basic_scene { 30 } else { 34 };
+            self.label_seed
+                .set_font(&font, seed_font_size)
+                .set_color(&WHITE)
+                .set_text(&self.seed_mode.label(self.seed))
+                .center(None, Some(self.label_announce.bottom() + 20.0), &playfield)
+                .draw();
         }
+        
     }
 }
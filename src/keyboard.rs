@@ -0,0 +1,47 @@
+use macroquad::prelude::*;
+use crate::sim::*;
+
+//=============================================================================
+//    KeyboardController
+//=============================================================================
+pub struct KeyboardController {
+    keys: [KeyCode; 4]  // Up, down, left, right
+}
+
+impl KeyboardController {
+    pub fn arrows() -> KeyboardController {
+        KeyboardController { keys: [KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right] }
+    }
+
+    pub fn wasd() -> KeyboardController {
+        KeyboardController { keys: [KeyCode::W, KeyCode::S, KeyCode::A, KeyCode::D] }
+    }
+
+    pub fn ijkl() -> KeyboardController {
+        KeyboardController { keys: [KeyCode::I, KeyCode::K, KeyCode::J, KeyCode::L] }
+    }
+
+    pub fn numpad() -> KeyboardController {
+        KeyboardController { keys: [KeyCode::Kp8, KeyCode::Kp5, KeyCode::Kp4, KeyCode::Kp6] }
+    }
+
+    // Arrows, WASD, IJKL and the number pad for players 1 to 4
+    pub fn for_player(id: usize) -> KeyboardController {
+        match id {
+            0 => KeyboardController::arrows(),
+            1 => KeyboardController::wasd(),
+            2 => KeyboardController::ijkl(),
+            _ => KeyboardController::numpad()
+        }
+    }
+}
+
+impl Controller for KeyboardController {
+    // First of the four keys held down, if any
+    fn next_direction(&mut self, _world: &World, _id: usize) -> Option<Direction> {
+        let directions = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+        self.keys.iter().zip(directions.iter())
+            .find(|(key, _)| is_key_down(**key))
+            .map(|(_, dir)| *dir)
+    }
+}
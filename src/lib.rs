@@ -0,0 +1,11 @@
+//=============================================================================
+//    snake_sim - the game rules as a library
+//=============================================================================
+// Runs games without a window, e.g. to train agents on sim::SnakeEnv:
+//   let mut env = SnakeEnv::new(23, 20, 1, Encoding::Features);
+//   let mut observation = env.reset(seed);
+//   loop {
+//       let (next, rewards, done) = env.step(&[action]);
+//       ...
+//   }
+pub mod sim;
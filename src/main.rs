@@ -1,44 +1,144 @@
+mod arena;
 mod assets;
 mod gamestate;
 mod gamescene;
 mod snake;
 mod apple;
 mod label;
-mod datapakloader;
 mod common;
+mod options;
+mod playback;
+mod seed;
+mod keyboard;
+mod bot;
+mod screen;
+mod campaign;
+mod editor;
 
 use macroquad::prelude::*;
+use macroquad::Window;
+use datapak::PakError;
+use arena::Arena;
 use gamestate::*;
+use options::*;
+use screen::VirtualScreen;
+use snake_sim::sim;
+use sim::Replay;
+use bot::BotController;
+use std::time::Duration;
 
-const WINDOW_WIDTH: f32 = 960.0;
-const WINDOW_HEIGHT: f32 = 640.0;
-const WIDTH: f32 = 23.0;
-const HEIGHT: f32 = 20.0;
-const GRID_SIZE: f32 = 32.0;
+// The window is opened by hand, as its size depends on the options
+fn main() {
+    let mut options = Options::load();
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    rand::srand(49152);
+    // A replay brings its own board size
+    let replay = match &options.replay_file {
+        Some(replay_file) => match Replay::load(replay_file) {
+            Ok(replay) => {
+                options.arena = options.arena.with_size(replay.width, replay.height);
+                Some(replay)
+            },
+            Err(e) => {
+                eprintln!("Error loading replay {}: {}", replay_file, e);
+                return;
+            }
+        },
+        None => None
+    };
 
+    Window::from_config(window_conf(&options.arena), run(options, replay));
+}
+
+async fn run(options: Options, replay: Option<Replay>) {
     println!("Screen size: {}x{}", screen_width(), screen_height());
 
-    let mut game = GameState::new(WIDTH, HEIGHT, GRID_SIZE);
-    game.load().await;
+    let arena = options.arena;
+    let mut screen = VirtualScreen::new(arena.window_width(), arena.window_height(), 
+                                        options.integer_scaling, options.fullscreen);
+    let mut game = GameState::new(arena, options.seed_mode, options.difficulty);
+    if let Err(e) = game.load(options.asset_source).await {
+        show_load_error(&e).await;
+        return;
+    }
+
+    if let Some(map) = &options.map {
+        if !game.select_map(map) {
+            eprintln!("Unknown map {}", map);
+        }
+    }
+
+    if options.wrap {
+        game.set_wrap(true);
+    }
+
+    if options.campaign && options.replay_file.is_none() {
+        game.start_campaign();
+    }
+
+    if let Some(replay) = replay {
+        if let Err(e) = game.start_playback(replay) {
+            eprintln!("Error playing replay: {}", e);
+            return;
+        }
+    }
+
+    for &player in &options.bot_players {
+        let port = match options.bot_port.checked_add(player as u16) {
+            Some(port) => port,
+            None => {
+                eprintln!("No bot port for player {}, {} is the last port", player + 1, u16::MAX);
+                return;
+            }
+        };
+        match BotController::listen(port, Duration::from_millis(options.bot_timeout_ms)) {
+            Ok(bot) => game.attach_bot(player, Box::new(bot)),
+            Err(e) => {
+                eprintln!("Error opening bot port {}: {}", port, e);
+                return;
+            }
+        }
+    }
+    // Enough players for every bot
+    match options.bot_players.iter().max() {
+        Some(&last_bot) if options.replay_file.is_none() && last_bot > 0 => game.reset(last_bot + 1),
+        _ => { }
+    }
     game.start();
 
     loop {
-        game.update();
+        game.reload_assets().await;
+        screen.handle_input();
+        game.update(screen.mouse_position());
+        let arena = game.arena();
+        screen.resize(arena.window_width(), arena.window_height());
+        screen.begin();
         game.draw();
+        screen.end();
+        next_frame().await;
+    }
+}
+
+// Fonts come from the assets too, so the error screen sticks to the built-in font
+async fn show_load_error(error: &PakError) {
+    eprintln!("Error loading game data: {}", error);
+    let message = error.to_string();
+
+    while !is_key_pressed(KeyCode::Escape) && !is_key_pressed(KeyCode::Enter) {
+        clear_background(BLACK);
+        draw_text("Missing or corrupt data", 40.0, 80.0, 48.0, RED);
+        draw_text("Game data could not be loaded:", 40.0, 140.0, 28.0, WHITE);
+        draw_text(&message, 40.0, 180.0, 28.0, WHITE);
+        draw_text("Press Esc to quit", 40.0, screen_height() - 40.0, 24.0, GRAY);
         next_frame().await;
     }
 }
 
-fn window_conf() -> Conf {
+fn window_conf(arena: &Arena) -> Conf {
     Conf {
         window_title: "Snake".to_owned(),
-        window_width: (WINDOW_WIDTH) as i32,
-        window_height: (WINDOW_HEIGHT) as i32, 
-        window_resizable: false,
+        window_width: arena.window_width() as i32,
+        window_height: arena.window_height() as i32, 
+        window_resizable: true,
         ..Default::default()
     }
 }
@@ -0,0 +1,276 @@
+use std::env;
+use std::fs;
+use serde::Deserialize;
+use crate::arena::Arena;
+use crate::assets::AssetSource;
+use crate::seed::SeedMode;
+use crate::sim::{Difficulty, World};
+use crate::bot::BotController;
+
+//=============================================================================
+//    Settings (settings.toml)
+//=============================================================================
+// Optional file next to the game, e.g.
+//   assets = "loose"
+//   seed = "daily"      # "random", "daily" or a number
+//   difficulty = "hard" # computer player: "easy", "normal" or "hard"
+//   arena = "large"     # "small", "medium", "large" or e.g. "40x30" cells
+//   grid_size = 24      # pixels per cell
+//   map = "pillars"     # a map from maps/ instead of the plain arena, or "random"
+//   wrap = true         # no outer wall, snakes come back in at the other side
+//   integer_scaling = true  # only enlarge the picture by whole factors
+//   fullscreen = true
+//   bots = [1, 2]       # players controlled over the bot socket
+//   bot_port = 7878     # player 1's port, the other players use the next ones
+//   bot_timeout = 50    # milliseconds after which a bot's answer is too late
+#[derive(Deserialize, Default)]
+struct Settings {
+    assets: Option<String>,
+    seed: Option<toml::Value>,
+    difficulty: Option<String>,
+    arena: Option<String>,
+    grid_size: Option<u32>,
+    map: Option<String>,
+    wrap: Option<bool>,
+    integer_scaling: Option<bool>,
+    fullscreen: Option<bool>,
+    bots: Option<Vec<u32>>,
+    bot_port: Option<u16>,
+    bot_timeout: Option<u64>
+}
+
+//=============================================================================
+//    Options (settings, environment and command line)
+//=============================================================================
+pub struct Options {
+    pub asset_source: AssetSource,
+    pub arena: Arena,
+    pub map: Option<String>,
+    pub wrap: bool,
+    pub campaign: bool,
+    pub integer_scaling: bool,
+    pub fullscreen: bool,
+    pub seed_mode: SeedMode,
+    pub difficulty: Difficulty,
+    pub replay_file: Option<String>,
+    pub bot_players: Vec<usize>,
+    pub bot_port: u16,
+    pub bot_timeout_ms: u64
+}
+
+impl Options {
+    const SETTINGS_FILE: &'static str = "settings.toml";
+    const ASSETS_ENV_VAR: &'static str = "SNAKE_ASSETS";
+
+    // Command line options win over the environment, which wins over settings.toml
+    //   --assets pak|loose     or SNAKE_ASSETS, defaults to the pak
+    //   --seed N|random|daily  apple sequence, defaults to a new random seed per game
+    //   --daily                same as --seed daily
+    //   --ai easy|normal|hard  difficulty of the computer player (F3)
+    //   --arena SIZE           small, medium, large or WIDTHxHEIGHT in cells
+    //   --grid-size N          pixels per cell
+    //   --map NAME             plays on maps/NAME.map, or random arenas for "random"
+    //                          (F9 cycles through the maps and random arenas)
+    //   --wrap                 free play boards wrap around at the edges
+    //   --campaign             starts the campaign (F12)
+    //   --integer-scaling      only enlarge the picture by whole factors (F10)
+    //   --fullscreen           start in fullscreen (F11)
+    //   --replay FILE          plays a recorded game instead of starting a new one
+    //   --bot 1-4              lets a bot control the player, can be repeated
+    //   --bot-port N           port for player 1's bot, player 2's is N + 1 and so on
+    //   --bot-timeout MS       time after which a bot's answer is too late
+    pub fn load() -> Options {
+        let mut options = Options {
+            asset_source: AssetSource::Pak,
+            arena: Arena::medium(),
+            map: None,
+            wrap: false,
+            campaign: false,
+            integer_scaling: false,
+            fullscreen: false,
+            seed_mode: SeedMode::Random,
+            difficulty: Difficulty::Normal,
+            replay_file: None,
+            bot_players: Vec::new(),
+            bot_port: BotController::DEFAULT_PORT,
+            bot_timeout_ms: BotController::DEFAULT_TIMEOUT_MS
+        };
+
+        let settings = Options::read_settings();
+        if let Some(name) = &settings.assets {
+            options.asset_source = Options::parse_source(name, options.asset_source);
+        }
+        match &settings.seed {
+            Some(toml::Value::Integer(seed)) if *seed >= 0 => {
+                options.seed_mode = SeedMode::Fixed(*seed as u64);
+            },
+            Some(toml::Value::String(name)) => {
+                options.seed_mode = Options::parse_seed(name, options.seed_mode);
+            },
+            Some(value) => println!("Ignoring seed setting: {}", value),
+            None => { }
+        }
+
+        if let Some(name) = &settings.difficulty {
+            options.difficulty = Options::parse_difficulty(name, options.difficulty);
+        }
+
+        if let Some(name) = &settings.arena {
+            options.arena = Options::parse_arena(name, options.arena);
+        }
+        if let Some(grid_size) = settings.grid_size {
+            options.arena = options.arena.with_grid_size(grid_size as f32);
+        }
+        options.map = settings.map.clone();
+        options.wrap = settings.wrap.unwrap_or(options.wrap);
+        options.integer_scaling = settings.integer_scaling.unwrap_or(options.integer_scaling);
+        options.fullscreen = settings.fullscreen.unwrap_or(options.fullscreen);
+
+        for player in settings.bots.iter().flatten() {
+            Options::add_bot(&mut options.bot_players, &player.to_string());
+        }
+        if let Some(port) = settings.bot_port {
+            options.bot_port = port;
+        }
+        if let Some(timeout) = settings.bot_timeout {
+            options.bot_timeout_ms = timeout;
+        }
+
+        if let Ok(name) = env::var(Options::ASSETS_ENV_VAR) {
+            options.asset_source = Options::parse_source(&name, options.asset_source);
+        }
+
+        let args: Vec<String> = env::args().skip(1).collect();
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--assets" if i + 1 < args.len() => {
+                    options.asset_source = Options::parse_source(&args[i + 1], options.asset_source);
+                    i += 1;
+                },
+                "--seed" if i + 1 < args.len() => {
+                    options.seed_mode = Options::parse_seed(&args[i + 1], options.seed_mode);
+                    i += 1;
+                },
+                "--daily" => options.seed_mode = SeedMode::Daily,
+                "--ai" if i + 1 < args.len() => {
+                    options.difficulty = Options::parse_difficulty(&args[i + 1], options.difficulty);
+                    i += 1;
+                },
+                "--arena" if i + 1 < args.len() => {
+                    options.arena = Options::parse_arena(&args[i + 1], options.arena);
+                    i += 1;
+                },
+                "--grid-size" if i + 1 < args.len() => {
+                    match args[i + 1].parse::<u32>() {
+                        Ok(grid_size) => options.arena = options.arena.with_grid_size(grid_size as f32),
+                        Err(_) => println!("Unknown grid size '{}', expected pixels", args[i + 1])
+                    }
+                    i += 1;
+                },
+                "--map" if i + 1 < args.len() => {
+                    options.map = Some(args[i + 1].clone());
+                    i += 1;
+                },
+                "--wrap" => options.wrap = true,
+                "--campaign" => options.campaign = true,
+                "--integer-scaling" => options.integer_scaling = true,
+                "--fullscreen" => options.fullscreen = true,
+                "--replay" if i + 1 < args.len() => {
+                    options.replay_file = Some(args[i + 1].clone());
+                    i += 1;
+                },
+                "--bot" if i + 1 < args.len() => {
+                    Options::add_bot(&mut options.bot_players, &args[i + 1]);
+                    i += 1;
+                },
+                "--bot-port" if i + 1 < args.len() => {
+                    match args[i + 1].parse() {
+                        Ok(port) => options.bot_port = port,
+                        Err(_) => println!("Unknown bot port '{}', expected a number", args[i + 1])
+                    }
+                    i += 1;
+                },
+                "--bot-timeout" if i + 1 < args.len() => {
+                    match args[i + 1].parse() {
+                        Ok(timeout) => options.bot_timeout_ms = timeout,
+                        Err(_) => println!("Unknown bot timeout '{}', expected milliseconds", args[i + 1])
+                    }
+                    i += 1;
+                },
+                arg => println!("Ignoring unknown argument: {}", arg)
+            }
+            i += 1;
+        }
+
+        options
+    }
+
+    // A missing file is normal, a broken one is reported and ignored
+    fn read_settings() -> Settings {
+        let text = match fs::read_to_string(Options::SETTINGS_FILE) {
+            Ok(text) => text,
+            Err(_) => return Settings::default()
+        };
+
+        match toml::from_str(&text) {
+            Ok(settings) => settings,
+            Err(e) => {
+                println!("Ignoring {}: {}", Options::SETTINGS_FILE, e);
+                Settings::default()
+            }
+        }
+    }
+
+    fn parse_source(name: &str, default: AssetSource) -> AssetSource {
+        match AssetSource::from_name(name) {
+            Some(source) => source,
+            None => {
+                println!("Unknown asset source '{}', expected pak or loose", name);
+                default
+            }
+        }
+    }
+
+    fn parse_seed(name: &str, default: SeedMode) -> SeedMode {
+        match SeedMode::from_name(name) {
+            Some(mode) => mode,
+            None => {
+                println!("Unknown seed '{}', expected a number, random or daily", name);
+                default
+            }
+        }
+    }
+
+    fn parse_arena(name: &str, default: Arena) -> Arena {
+        match default.with_size_name(name) {
+            Some(arena) => arena,
+            None => {
+                println!("Unknown arena '{}', expected small, medium, large or WIDTHxHEIGHT", name);
+                default
+            }
+        }
+    }
+
+    fn parse_difficulty(name: &str, default: Difficulty) -> Difficulty {
+        match Difficulty::from_name(name) {
+            Some(difficulty) => difficulty,
+            None => {
+                println!("Unknown difficulty '{}', expected easy, normal or hard", name);
+                default
+            }
+        }
+    }
+
+    // Players are numbered from 1 like on screen, stored from 0
+    fn add_bot(bot_players: &mut Vec<usize>, name: &str) {
+        match name.parse::<usize>() {
+            Ok(player) if (1..=World::MAX_PLAYERS).contains(&player) => {
+                if !bot_players.contains(&(player - 1)) {
+                    bot_players.push(player - 1);
+                }
+            },
+            _ => println!("Unknown bot player '{}', expected 1 to {}", name, World::MAX_PLAYERS)
+        }
+    }
+}
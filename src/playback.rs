@@ -0,0 +1,66 @@
+use macroquad::prelude::*;
+use crate::sim::*;
+
+//=============================================================================
+//    Playback
+//=============================================================================
+// Drives the world from a replay instead of the keyboard.
+//   Space  pause / resume
+//   F      fast-forward (1x, 2x, 4x, 8x)
+//   .      step one tick while paused
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+    paused: bool,
+    speed: u32
+}
+
+impl Playback {
+    const MAX_SPEED: u32 = 8;
+
+    pub fn new(replay: Replay) -> Playback {
+        Playback { replay, tick: 0, paused: false, speed: 1 }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn rewind(&mut self) {
+        self.tick = 0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.tick_count()
+    }
+
+    // Returns the number of ticks to run right now when paused and stepping
+    pub fn handle_input(&mut self) -> Option<u32> {
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        } else if is_key_pressed(KeyCode::F) {
+            self.speed = if self.speed >= Playback::MAX_SPEED { 1 } else { self.speed * 2 };
+        } else if is_key_pressed(KeyCode::Period) && self.paused {
+            return Some(1);
+        }
+        None
+    }
+
+    // Inputs for the next tick, nothing once the recording has ended
+    pub fn next_inputs(&mut self) -> Vec<Option<Direction>> {
+        let inputs = match self.replay.tick_inputs(self.tick) {
+            Some(inputs) => inputs.to_vec(),
+            None => vec![None; self.replay.player_count]
+        };
+        self.tick += 1;
+        inputs
+    }
+}
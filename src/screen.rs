@@ -0,0 +1,105 @@
+use macroquad::prelude::*;
+
+//=============================================================================
+//    VirtualScreen
+//=============================================================================
+// The game draws to a fixed size target, which is then scaled to fit the
+// window with black bars around it. With integer scaling the pixel art is
+// only ever enlarged by whole factors, so every texel stays square.
+pub struct VirtualScreen {
+    width: f32,
+    height: f32,
+    target: RenderTarget,
+    integer_scaling: bool,
+    fullscreen: bool
+}
+
+impl VirtualScreen {
+    pub fn new(width: f32, height: f32, integer_scaling: bool, fullscreen: bool) -> VirtualScreen {
+        let target = render_target(width as u32, height as u32);
+        let mut screen = VirtualScreen { width, height, target, integer_scaling, fullscreen };
+        screen.set_integer_scaling(integer_scaling);
+        if fullscreen {
+            set_fullscreen(true);
+        }
+        screen
+    }
+
+    // A new size also asks for a window of that size, outside fullscreen
+    pub fn resize(&mut self, width: f32, height: f32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+
+        self.target.delete();
+        self.width = width;
+        self.height = height;
+        self.target = render_target(width as u32, height as u32);
+        self.set_integer_scaling(self.integer_scaling);
+        if !self.fullscreen {
+            request_new_screen_size(width, height);
+        }
+    }
+
+    // F11 toggles fullscreen, F10 integer scaling
+    pub fn handle_input(&mut self) {
+        if is_key_pressed(KeyCode::F11) {
+            self.fullscreen = !self.fullscreen;
+            set_fullscreen(self.fullscreen);
+        } else if is_key_pressed(KeyCode::F10) {
+            self.set_integer_scaling(!self.integer_scaling);
+        }
+    }
+
+    // Everything drawn until end() goes to the virtual screen, in its pixels
+    pub fn begin(&self) {
+        set_camera(&Camera2D {
+            target: vec2(self.width / 2.0, self.height / 2.0),
+            // Positive y zoom, as the target's rows end up flipped when drawn
+            zoom: vec2(2.0 / self.width, 2.0 / self.height),
+            render_target: Some(self.target),
+            ..Default::default()
+        });
+    }
+
+    pub fn end(&self) {
+        set_default_camera();
+        clear_background(BLACK);
+
+        let viewport = self.viewport();
+        draw_texture_ex(self.target.texture, viewport.x, viewport.y, WHITE, DrawTextureParams {
+            dest_size: Some(Vec2::new(viewport.w, viewport.h)),
+            ..Default::default()
+        });
+    }
+
+    // The mouse in the pixels of the virtual screen
+    pub fn mouse_position(&self) -> Vec2 {
+        let viewport = self.viewport();
+        let (x, y) = mouse_position();
+        Vec2::new((x - viewport.x) * self.width / viewport.w, 
+                  (y - viewport.y) * self.height / viewport.h)
+    }
+
+    // Where the virtual screen ends up in the window
+    pub fn viewport(&self) -> Rect {
+        let mut scale = (screen_width() / self.width).min(screen_height() / self.height);
+        if self.integer_scaling && scale >= 1.0 {
+            scale = scale.floor();
+        }
+
+        let w = self.width * scale;
+        let h = self.height * scale;
+        Rect::new(((screen_width() - w) / 2.0).floor(), ((screen_height() - h) / 2.0).floor(), w, h)
+    }
+
+//=============================================================================
+//  Private methods (VirtualScreen)
+//=============================================================================
+    // Smooth filtering hides the uneven texel sizes of fractional scales
+    fn set_integer_scaling(&mut self, integer_scaling: bool) {
+        self.integer_scaling = integer_scaling;
+        self.target.texture.set_filter(
+            if integer_scaling { FilterMode::Nearest } else { FilterMode::Linear });
+    }
+}
@@ -0,0 +1,110 @@
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//=============================================================================
+//    SeedMode
+//=============================================================================
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SeedMode {
+    Random,      // A new seed from the clock for every game
+    Fixed(u64),  // The same apples every game
+    Daily        // The UTC date as YYYYMMDD, the same for everyone on a day
+}
+
+impl SeedMode {
+    // "random", "daily" or a number
+    pub fn from_name(name: &str) -> Option<SeedMode> {
+        match name {
+            "random" => Some(SeedMode::Random),
+            "daily" => Some(SeedMode::Daily),
+            _ => name.parse().ok().map(SeedMode::Fixed)
+        }
+    }
+
+    pub fn next_seed(&self) -> u64 {
+        match self {
+            SeedMode::Random => time_seed(),
+            SeedMode::Fixed(seed) => *seed,
+            SeedMode::Daily => daily_seed()
+        }
+    }
+
+    pub fn label(&self, seed: u64) -> String {
+        match self {
+            SeedMode::Daily => format!("Daily seed {}", seed),
+            _ => format!("Seed {}", seed)
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+// Kept below 10^9 so it is easy to read off the screen and type in again
+fn time_seed() -> u64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos() as u64)
+        .unwrap_or(0);
+    (unix_time() ^ (nanos << 7) ^ process::id() as u64) % 1_000_000_000
+}
+
+fn daily_seed() -> u64 {
+    date_seed((unix_time() / 86400) as i64)
+}
+
+fn date_seed(days: i64) -> u64 {
+    let (year, month, day) = civil_from_days(days);
+    (year * 10000 + month * 100 + day) as u64
+}
+
+// Days since 1970-01-01 to a (year, month, day) date, using Howard Hinnant's
+// civil_from_days algorithm
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 
+                       - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(10956), (1999, 12, 31));
+        assert_eq!(civil_from_days(10957), (2000, 1, 1));
+        assert_eq!(civil_from_days(11015), (2000, 2, 28));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(20088), (2024, 12, 31));
+        assert_eq!(civil_from_days(20089), (2025, 1, 1));
+    }
+
+    #[test]
+    fn daily_seed_is_the_date() {
+        assert_eq!(date_seed(11016), 20000229);
+        assert_eq!(date_seed(0), 19700101);
+    }
+
+    #[test]
+    fn parses_seed_names() {
+        assert_eq!(SeedMode::from_name("random"), Some(SeedMode::Random));
+        assert_eq!(SeedMode::from_name("daily"), Some(SeedMode::Daily));
+        assert_eq!(SeedMode::from_name("42"), Some(SeedMode::Fixed(42)));
+        assert_eq!(SeedMode::from_name("-1"), None);
+        assert_eq!(SeedMode::Fixed(42).next_seed(), 42);
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+use crate::sim::*;
+
+//=============================================================================
+//    Grid
+//=============================================================================
+// For every cell the number of moves until a snake may enter it: 0 for free
+// cells, NEVER for walls and dead snakes. Collisions are checked before the
+// tail moves on, so even a tail cell only opens up on the second move. Other
+// snakes are assumed to move as often as the one planning. On a wrapping
+// board the steps go across the edges.
+const NEVER: u32 = u32::MAX;
+
+#[derive(Clone)]
+struct Grid {
+    width: i32,
+    height: i32,
+    wraps: bool,
+    free_after: Vec<u32>
+}
+
+impl Grid {
+    fn new(board: &Board) -> Grid {
+        let mut grid = Grid {
+            width: board.width(),
+            height: board.height(),
+            wraps: board.wraps(),
+            free_after: vec![NEVER; (board.width() * board.height()) as usize]
+        };
+
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let pos = Pos::new(x, y);
+                if board.play_area_contains(&pos) {
+                    let i = grid.index(&pos).unwrap();
+                    grid.free_after[i] = 0;
+                }
+            }
+        }
+        grid
+    }
+
+    fn from_world(world: &World) -> Grid {
+        let mut grid = Grid::new(world.board());
+        for snake in world.snakes() {
+            let cells: Vec<Pos> = snake.parts().map(|part| part.pos).collect();
+            grid.add_body(&cells, snake.is_alive(), 0);
+        }
+        grid
+    }
+
+    // Body cells head first, as they will be after `elapsed` more moves
+    fn add_body(&mut self, cells: &[Pos], moving: bool, elapsed: u32) {
+        let length = cells.len() as u32;
+        for (k, pos) in cells.iter().enumerate() {
+            let free_after = if moving { 
+                (length - k as u32 + 1).saturating_sub(elapsed) 
+            } else { 
+                NEVER 
+            };
+            if let Some(i) = self.index(pos) {
+                self.free_after[i] = self.free_after[i].max(free_after);
+            }
+        }
+    }
+
+    fn index(&self, pos: &Pos) -> Option<usize> {
+        if pos.x >= 0 && pos.x < self.width && pos.y >= 0 && pos.y < self.height {
+            Some((pos.y * self.width + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn pos(&self, index: usize) -> Pos {
+        Pos::new(index as i32 % self.width, index as i32 / self.width)
+    }
+
+    // Like Board::next_position
+    fn next(&self, pos: &Pos, dir: Direction) -> Pos {
+        let next = pos.offset(dir);
+        if self.wraps {
+            Pos::new(next.x.rem_euclid(self.width), next.y.rem_euclid(self.height))
+        } else {
+            next
+        }
+    }
+
+    // Moves between two cells with nothing in the way
+    fn distance(&self, from: &Pos, to: &Pos) -> i32 {
+        let dx = (to.x - from.x).abs();
+        let dy = (to.y - from.y).abs();
+        if self.wraps {
+            dx.min(self.width - dx) + dy.min(self.height - dy)
+        } else {
+            dx + dy
+        }
+    }
+
+    // Can the cell be entered on the given move
+    fn is_open(&self, pos: &Pos, step: u32) -> bool {
+        match self.index(pos) {
+            Some(i) => self.free_after[i] <= step,
+            None => false
+        }
+    }
+
+    // Breadth-first search from start; cells open up as the bodies move
+    fn paths(&self, start: &Pos) -> Paths {
+        let mut paths = Paths {
+            distance: vec![NEVER; self.free_after.len()],
+            parent: vec![usize::MAX; self.free_after.len()]
+        };
+        let start_index = match self.index(start) {
+            Some(i) => i,
+            None => return paths
+        };
+
+        let mut queue = VecDeque::new();
+        paths.distance[start_index] = 0;
+        queue.push_back(start_index);
+
+        while let Some(i) = queue.pop_front() {
+            let step = paths.distance[i] + 1;
+            for dir in DIRECTIONS.iter() {
+                let next = self.next(&self.pos(i), *dir);
+                if !self.is_open(&next, step) {
+                    continue;
+                }
+                let j = self.index(&next).unwrap();
+                if paths.distance[j] == NEVER {
+                    paths.distance[j] = step;
+                    paths.parent[j] = i;
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        paths
+    }
+}
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+struct Paths {
+    distance: Vec<u32>,
+    parent: Vec<usize>
+}
+
+impl Paths {
+    fn distance(&self, grid: &Grid, pos: &Pos) -> Option<u32> {
+        grid.index(pos).map(|i| self.distance[i]).filter(|distance| *distance != NEVER)
+    }
+
+    fn reachable_count(&self) -> usize {
+        self.distance.iter().filter(|distance| **distance != NEVER).count()
+    }
+
+    // Cells from the first step to the target
+    fn path(&self, grid: &Grid, target: &Pos) -> Vec<Pos> {
+        let mut path = Vec::new();
+        let mut i = match grid.index(target) {
+            Some(i) if self.distance[i] != NEVER => i,
+            _ => return path
+        };
+
+        while self.distance[i] != 0 {
+            path.push(grid.pos(i));
+            i = self.parent[i];
+        }
+        path.reverse();
+        path
+    }
+}
+
+fn direction_between(grid: &Grid, from: &Pos, to: &Pos) -> Option<Direction> {
+    DIRECTIONS.iter().copied().find(|dir| grid.next(from, *dir) == *to)
+}
+
+// Number of cells the snake could still reach after stepping into next
+fn space_after(grid: &Grid, next: &Pos) -> usize {
+    if !grid.is_open(next, 1) {
+        return 0;
+    }
+    grid.paths(next).reachable_count()
+}
+
+//=============================================================================
+//    GreedyAi
+//=============================================================================
+pub struct GreedyAi;
+
+impl Controller for GreedyAi {
+    fn next_direction(&mut self, world: &World, id: usize) -> Option<Direction> {
+        let snake = world.snake(id);
+        if !snake.is_alive() {
+            return None;
+        }
+        let head = snake.position();
+        let grid = Grid::from_world(world);
+        let current = snake.direction();
+
+        // Straight on first, so ties keep the current direction
+        let mut candidates = vec![current];
+        candidates.extend(DIRECTIONS.iter().filter(|dir| **dir != current 
+                                                   && **dir != opposite_dir(current)));
+
+        candidates.into_iter()
+            .filter(|dir| grid.is_open(&grid.next(&head, *dir), 1))
+            .min_by_key(|dir| {
+                let next = grid.next(&head, *dir);
+                world.apples().iter()
+                    .map(|apple| grid.distance(&next, &apple.pos))
+                    .min()
+                    .unwrap_or(0)
+            })
+    }
+}
+
+//=============================================================================
+//    PathAi
+//=============================================================================
+pub struct PathAi;
+
+impl PathAi {
+    // Shortest way to an apple, taken only if the tail can still be reached
+    // from the apple afterwards; otherwise chase the tail, otherwise head for
+    // the most room
+    fn plan(world: &World, id: usize, grid: &Grid) -> Option<Direction> {
+        let snake = world.snake(id);
+        let head = snake.position();
+        let paths = grid.paths(&head);
+
+        let nearest_apple = world.apples().iter()
+            .filter_map(|apple| paths.distance(grid, &apple.pos).map(|distance| (distance, apple.pos)))
+            .filter(|(distance, _)| *distance > 0)
+            .min_by_key(|(distance, _)| *distance);
+
+        if let Some((_, apple)) = nearest_apple {
+            let path = paths.path(grid, &apple);
+            if PathAi::can_escape(world, id, &path) {
+                return direction_between(grid, &head, &path[0]);
+            }
+        }
+
+        if let Some(tail) = snake.parts().last() {
+            let path = paths.path(grid, &tail.pos);
+            if !path.is_empty() {
+                return direction_between(grid, &head, &path[0]);
+            }
+        }
+
+        PathAi::most_space(grid, &head)
+    }
+
+    fn can_escape(world: &World, id: usize, path: &[Pos]) -> bool {
+        let snake = world.snake(id);
+        let elapsed = path.len() as u32;
+
+        // The body as it will be right after eating: the path, newest first,
+        // followed by the old body, one part longer
+        let body: Vec<Pos> = path.iter().rev().copied()
+            .chain(snake.parts().map(|part| part.pos))
+            .take(snake.length() as usize + 1)
+            .collect();
+
+        let mut grid = Grid::new(world.board());
+        for (i, other) in world.snakes().iter().enumerate() {
+            if i != id {
+                let cells: Vec<Pos> = other.parts().map(|part| part.pos).collect();
+                grid.add_body(&cells, other.is_alive(), elapsed);
+            }
+        }
+        grid.add_body(&body, true, 0);
+
+        let tail = body[body.len() - 1];
+        grid.paths(&body[0]).distance(&grid, &tail).is_some()
+    }
+
+    fn most_space(grid: &Grid, head: &Pos) -> Option<Direction> {
+        DIRECTIONS.iter().copied()
+            .map(|dir| (dir, space_after(grid, &grid.next(head, dir))))
+            .filter(|(_, space)| *space > 0)
+            .max_by_key(|(_, space)| *space)
+            .map(|(dir, _)| dir)
+    }
+}
+
+impl Controller for PathAi {
+    fn next_direction(&mut self, world: &World, id: usize) -> Option<Direction> {
+        if !world.snake(id).is_alive() {
+            return None;
+        }
+        PathAi::plan(world, id, &Grid::from_world(world))
+    }
+}
+
+//=============================================================================
+//    AggressiveAi
+//=============================================================================
+pub struct AggressiveAi;
+
+impl AggressiveAi {
+    // How far ahead of the opponent's head to look for a cell to cut it off
+    const CUT_OFF_RANGE: i32 = 6;
+}
+
+impl Controller for AggressiveAi {
+    fn next_direction(&mut self, world: &World, id: usize) -> Option<Direction> {
+        let snake = world.snake(id);
+        if !snake.is_alive() {
+            return None;
+        }
+        let grid = Grid::from_world(world);
+        let head = snake.position();
+        let paths = grid.paths(&head);
+
+        let opponents = world.snakes().iter().enumerate()
+            .filter(|(i, other)| *i != id && other.is_alive())
+            .map(|(_, other)| other);
+
+        for opponent in opponents {
+            let opponent_dir = opponent.direction();
+            let mut cell = opponent.position();
+
+            // A cell in front of the opponent that we reach no later than it does
+            for k in 1..=AggressiveAi::CUT_OFF_RANGE {
+                cell = grid.next(&cell, opponent_dir);
+                if !world.board().play_area_contains(&cell) {
+                    break;
+                }
+
+                let distance = match paths.distance(&grid, &cell) {
+                    Some(distance) if distance > 0 => distance,
+                    _ => continue
+                };
+                if distance as i32 > k {
+                    continue;
+                }
+
+                let path = paths.path(&grid, &cell);
+                if space_after(&grid, &path[0]) >= snake.length() as usize {
+                    return direction_between(&grid, &head, &path[0]);
+                }
+            }
+        }
+
+        PathAi::plan(world, id, &grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Snake 0 comes in at the bottom and stops at (3, 5) heading up, next to
+    // a pocket of two cells on its left
+    const MAP: &str = "\
+#########
+#.......#
+#.......#
+#.......#
+###.....#
+#.......#
+###.....#
+#.......#
+###1#2###
+";
+
+    fn new_world(apples: &[Pos]) -> World {
+        let board = Board::parse_map("Test", MAP).unwrap();
+        let mut world = World::with_board(board, 1);
+        world.reset(1, 1);
+        while world.state() != LevelState::Playing {
+            world.step(&[]);
+        }
+        assert_eq!(world.snake(0).position(), Pos::new(3, 5));
+        assert_eq!(world.snake(0).direction(), Direction::Up);
+        world.set_apples(apples);
+        world
+    }
+
+    #[test]
+    fn greedy_heads_for_the_apple() {
+        let world = new_world(&[Pos::new(6, 5)]);
+        assert_eq!(GreedyAi.next_direction(&world, 0), Some(Direction::Right));
+        let world = new_world(&[Pos::new(3, 1)]);
+        assert_eq!(GreedyAi.next_direction(&world, 0), Some(Direction::Up));
+        let world = new_world(&[Pos::new(1, 5)]);
+        assert_eq!(GreedyAi.next_direction(&world, 0), Some(Direction::Left));
+    }
+
+    #[test]
+    fn path_ai_takes_the_shortest_way() {
+        let world = new_world(&[Pos::new(6, 5)]);
+        assert_eq!(PathAi.next_direction(&world, 0), Some(Direction::Right));
+        let world = new_world(&[Pos::new(3, 1)]);
+        assert_eq!(PathAi.next_direction(&world, 0), Some(Direction::Up));
+    }
+
+    #[test]
+    fn path_ai_stays_out_of_pockets_smaller_than_its_body() {
+        // Two cells, the snake is three long and one more after eating
+        let world = new_world(&[Pos::new(1, 5)]);
+        for controller in [Difficulty::Normal.new_ai(), Difficulty::Hard.new_ai()].iter_mut() {
+            let dir = controller.next_direction(&world, 0);
+            assert!(dir.is_some());
+            assert_ne!(dir, Some(Direction::Left));
+        }
+    }
+
+    #[test]
+    fn no_computer_player_turns_back() {
+        for &difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard].iter() {
+            for &wrap in [false, true].iter() {
+                let mut world = World::new(17, 15, 3);
+                world.set_wrap(wrap);
+                world.reset(2, 3);
+                let mut players = [difficulty.new_ai(), difficulty.new_ai()];
+                while world.state() != LevelState::GameOver && world.tick_count() < 2000 {
+                    let mut inputs = Vec::new();
+                    for (id, player) in players.iter_mut().enumerate() {
+                        let dir = player.next_direction(&world, id);
+                        let snake = world.snake(id);
+                        if world.state() == LevelState::Playing && snake.is_alive() {
+                            assert_ne!(dir, Some(opposite_dir(snake.direction())), "{:?}", difficulty);
+                        }
+                        inputs.push(dir);
+                    }
+                    world.step(&inputs);
+                }
+            }
+        }
+    }
+}
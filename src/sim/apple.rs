@@ -0,0 +1,35 @@
+use crate::sim::*;
+
+//=================================================================================================
+//    Apple
+//=================================================================================================
+pub struct Apple {
+    pub pos: Pos,
+    pub age: u32  // Ticks since spawning
+}
+
+impl Apple {
+    pub fn random_spawn(board: &Board, snakes: &[Snake], rng: &mut Rng) -> Apple {
+        let mut pos: Pos;
+        let mut retry_count: u32 = 0;
+
+        loop {
+            pos = board.random_apple_position(rng);
+
+            // Do not spawn on top of the snake
+            let overlap_snakes = snakes.iter().any(|snake| snake.has_position(&pos));
+            if overlap_snakes && retry_count < 20 {
+                retry_count += 1;
+                continue;
+            }
+
+            break;
+        }
+
+        Apple { pos, age: 0 }
+    }
+
+    pub fn update(&mut self) {
+        self.age += 1;
+    }
+}
@@ -0,0 +1,269 @@
+use crate::sim::Rng;
+
+//=============================================================================
+//    Pos
+//=============================================================================
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Pos {
+    pub x: i32,
+    pub y: i32
+}
+
+impl Pos {
+    pub fn new(x: i32, y: i32) -> Pos {
+        Pos { x, y }
+    }
+
+    pub fn offset(&self, dir: Direction) -> Pos {
+        let offset = dir_offset(dir);
+        Pos::new(self.x + offset.x, self.y + offset.y)
+    }
+}
+
+//=============================================================================
+//    Direction
+//=============================================================================
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Up, Down, Left, Right 
+}
+
+pub fn dir_offset(dir: Direction) -> Pos {
+    match dir {
+        Direction::Up => Pos::new(0, -1), 
+        Direction::Down => Pos::new(0, 1), 
+        Direction::Left => Pos::new(-1, 0), 
+        Direction::Right => Pos::new(1, 0) 
+    }
+}
+
+pub fn opposite_dir(dir: Direction) -> Direction {
+    match dir {
+        Direction::Up => Direction::Down,
+        Direction::Down => Direction::Up, 
+        Direction::Left =>  Direction::Right,
+        Direction::Right => Direction::Left,
+    }
+}
+
+//=============================================================================
+//    Board
+//=============================================================================
+// The arena in cells: walls around the play area, with a gate per player
+// where the snakes enter. The built-in arena is an empty rectangle with two
+// gates in the bottom wall and two in the top; maps add walls inside, place
+// the gates anywhere in the outer wall and can limit where apples grow.
+// A wrapping board has no outer wall: what leaves one edge comes back in at
+// the opposite one.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Gate {
+    pub pos: Pos,
+    pub dir: Direction  // The way snakes come in
+}
+
+#[derive(Clone)]
+pub struct Board {
+    name: String,  // Empty for the built-in arena
+    width: i32,
+    height: i32,
+    walls: Vec<bool>,
+    gates: Vec<Gate>,
+    apple_zone: Vec<Pos>,
+    gate_count: usize,
+    gates_opened: bool,
+    wraps: bool
+}
+
+impl Board {
+    pub const MAX_GATES: usize = 4;
+
+    pub fn new(width: i32, height: i32) -> Board {
+        let mut board = Board::empty(String::new(), width, height);
+        let left_gate = board.left_gate_position();
+        let right_gate = board.right_gate_position();
+
+        // Players 1 and 2 come in from the bottom, 3 and 4 from the top
+        board.gates = vec![
+            Gate { pos: right_gate, dir: Direction::Up },
+            Gate { pos: left_gate, dir: Direction::Up },
+            Gate { pos: Pos::new(left_gate.x, 0), dir: Direction::Down },
+            Gate { pos: Pos::new(right_gate.x, 0), dir: Direction::Down }
+        ];
+        board
+    }
+
+    // Only walls around the border, no gates yet
+    pub(crate) fn empty(name: String, width: i32, height: i32) -> Board {
+        let mut walls = vec![false; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    walls[(y * width + x) as usize] = true;
+                }
+            }
+        }
+
+        Board { 
+            name, width, height, walls, 
+            gates: Vec::new(), 
+            apple_zone: Vec::new(), 
+            gate_count: 2, 
+            gates_opened: true,
+            wraps: false
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn left_gate_position(&self) -> Pos {
+        Pos::new(3, self.height - 1)
+    }
+
+    pub fn right_gate_position(&self) -> Pos {
+        Pos::new(self.width - 1 - 3, self.height - 1)
+    }
+
+    // Players beyond the gates there are share them
+    pub fn gate(&self, id: usize) -> Gate {
+        self.gates[id % self.gates.len()]
+    }
+
+    // The gates in use, one per player
+    pub fn gates(&self) -> impl Iterator<Item = Gate> + '_ {
+        (0..self.gate_count).map(move |id| self.gate(id))
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.gates.len()
+    }
+
+    pub fn set_gate_count(&mut self, gate_count: usize) {
+        self.gate_count = gate_count.min(self.gates.len());
+    }
+
+    pub fn gates_opened(&self) -> bool {
+        self.gates_opened
+    }
+
+    pub fn open_gates(&mut self) {
+        self.gates_opened = true;
+    }
+
+    pub fn close_gates(&mut self) {
+        self.gates_opened = false;
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wraps
+    }
+
+    // Opens up the outer wall, gates included, or puts it back. The whole
+    // border goes, so walls a map has there besides its gates are lost too,
+    // and wrap off walls in the whole border again.
+    pub(crate) fn set_wrap(&mut self, wrap: bool) {
+        self.wraps = wrap;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1 {
+                    self.set_wall(&Pos::new(x, y), !wrap);
+                }
+            }
+        }
+    }
+
+    // The cell a step away in the given direction, across the edge on a
+    // wrapping board
+    pub fn next_position(&self, pos: &Pos, dir: Direction) -> Pos {
+        let next = pos.offset(dir);
+        if self.wraps {
+            Pos::new(next.x.rem_euclid(self.width), next.y.rem_euclid(self.height))
+        } else {
+            next
+        }
+    }
+
+    pub fn apple_zone(&self) -> &[Pos] {
+        &self.apple_zone
+    }
+
+    // Walls, including the outer wall and gates; anything off the board too
+    pub fn is_wall(&self, pos: &Pos) -> bool {
+        match self.index(pos) {
+            Some(i) => self.walls[i],
+            None => true
+        }
+    }
+
+    // Cells the snakes can move in, the gates in use while they are open
+    pub fn play_area_contains(&self, pos: &Pos) -> bool {
+        !self.is_wall(pos) || (self.gates_opened && self.gates().any(|gate| gate.pos == *pos))
+    }
+
+    pub fn random_play_position(&self, rng: &mut Rng) -> Pos {
+        if self.wraps {
+            let x = (rng.rand() % self.width as u32) as i32;
+            let y = (rng.rand() % self.height as u32) as i32;
+            return Pos::new(x, y);
+        }
+
+        let x = (rng.rand() % (self.width - 2) as u32) as i32;
+        let y = (rng.rand() % (self.height - 2) as u32) as i32;
+        // +1 to start after left & top border
+        Pos::new(x + 1, y + 1)
+    }
+
+    // In the apple zone if the map has one, otherwise anywhere off the walls
+    pub fn random_apple_position(&self, rng: &mut Rng) -> Pos {
+        if !self.apple_zone.is_empty() {
+            return self.apple_zone[rng.rand() as usize % self.apple_zone.len()];
+        }
+
+        loop {
+            let pos = self.random_play_position(rng);
+            if self.play_area_contains(&pos) {
+                return pos;
+            }
+        }
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub(crate) fn set_wall(&mut self, pos: &Pos, wall: bool) {
+        if let Some(i) = self.index(pos) {
+            self.walls[i] = wall;
+        }
+    }
+
+    pub(crate) fn add_gate(&mut self, gate: Gate) {
+        self.set_wall(&gate.pos, true);
+        self.gates.push(gate);
+    }
+
+    pub(crate) fn add_apple_zone(&mut self, pos: Pos) {
+        self.set_wall(&pos, false);
+        self.apple_zone.push(pos);
+    }
+
+//=============================================================================
+//  Private methods (Board)
+//=============================================================================
+    fn index(&self, pos: &Pos) -> Option<usize> {
+        if pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height {
+            Some((pos.y * self.width + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+}
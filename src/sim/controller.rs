@@ -0,0 +1,55 @@
+use crate::sim::*;
+
+//=============================================================================
+//    Controller
+//=============================================================================
+// Decides where a snake wants to go. Asked once per tick before World::step,
+// so it always sees the world the step will start from.
+pub trait Controller {
+    fn next_direction(&mut self, world: &World, id: usize) -> Option<Direction>;
+}
+
+//=============================================================================
+//    Difficulty
+//=============================================================================
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,    // Heads for the nearest apple, no look-ahead
+    Normal,  // Shortest path to an apple it can get away from, else chases its tail
+    Hard     // Like Normal, but cuts off the opponent when it can get in front
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard"
+        }
+    }
+
+    pub fn next(&self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy
+        }
+    }
+
+    pub fn new_ai(&self) -> Box<dyn Controller> {
+        match self {
+            Difficulty::Easy => Box::new(GreedyAi),
+            Difficulty::Normal => Box::new(PathAi),
+            Difficulty::Hard => Box::new(AggressiveAi)
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::sim::*;
+
+//=============================================================================
+//    SnakeEnv
+//=============================================================================
+// The world as a reinforcement learning environment. One step is one move:
+// the actions are handed to the world and ticks run until a snake has moved,
+// so agents never see the ticks in between. The get ready and entering
+// phases are skipped, and an episode ends at the first crash or after
+// max_steps moves.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Encoding {
+    // GRID_CHANNELS x height x width, one plane each for walls, apples, the
+    // player's head, the player's body, other heads and other bodies
+    Grid,
+    // FEATURE_COUNT values, see SnakeEnv::features
+    Features
+}
+
+// One flattened vector per player, all of the same shape
+pub struct Observation {
+    pub shape: Vec<usize>,
+    pub players: Vec<Vec<f32>>
+}
+
+pub struct SnakeEnv {
+    world: World,
+    encoding: Encoding,
+    max_steps: u32,
+    steps: u32
+}
+
+impl SnakeEnv {
+    pub const GRID_CHANNELS: usize = 6;
+    pub const FEATURE_COUNT: usize = 16;
+    pub const APPLE_REWARD: f32 = 1.0;
+    pub const CRASH_REWARD: f32 = -1.0;
+    const DEFAULT_MAX_STEPS: u32 = 10_000;
+
+    pub fn new(width: i32, height: i32, player_count: usize, encoding: Encoding) -> SnakeEnv {
+        let mut world = World::new(width, height, 0);
+        world.reset(player_count, 0);
+        SnakeEnv { world, encoding, max_steps: SnakeEnv::DEFAULT_MAX_STEPS, steps: 0 }
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps;
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.world.player_count()
+    }
+
+    pub fn observation_shape(&self) -> Vec<usize> {
+        match self.encoding {
+            Encoding::Grid => {
+                let board = self.world.board();
+                vec![SnakeEnv::GRID_CHANNELS, board.height() as usize, board.width() as usize]
+            },
+            Encoding::Features => vec![SnakeEnv::FEATURE_COUNT]
+        }
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.world.reset(self.world.player_count(), seed);
+        self.steps = 0;
+        while self.world.state() != LevelState::Playing
+                && self.world.state() != LevelState::GameOver {
+            self.world.step(&[]);
+        }
+        self.observe()
+    }
+
+    // actions[i] turns player i, None goes straight on. Returns the new
+    // observation, the reward of every player and whether the episode is over.
+    pub fn step(&mut self, actions: &[Option<Direction>]) -> (Observation, Vec<f32>, bool) {
+        let mut rewards = vec![0.0; self.world.player_count()];
+        let move_counts = self.move_counts();
+        let mut inputs = actions.to_vec();
+
+        // Turns are only taken on the first tick, they stick until the move
+        loop {
+            for event in self.world.step(&inputs) {
+                match event {
+                    Event::AteApple(i) => rewards[i] += SnakeEnv::APPLE_REWARD,
+                    Event::Crashed(i, _) => rewards[i] += SnakeEnv::CRASH_REWARD,
+                    _ => { }
+                }
+            }
+            inputs.clear();
+
+            if self.world.state() != LevelState::Playing || self.move_counts() != move_counts {
+                break;
+            }
+        }
+
+        self.steps += 1;
+        let done = self.world.state() != LevelState::Playing || self.steps >= self.max_steps;
+        (self.observe(), rewards, done)
+    }
+
+    pub fn observe(&self) -> Observation {
+        let players = (0..self.world.player_count())
+            .map(|id| match self.encoding {
+                Encoding::Grid => self.grid(id),
+                Encoding::Features => self.features(id)
+            })
+            .collect();
+        Observation { shape: self.observation_shape(), players }
+    }
+
+//=============================================================================
+//  Private methods (SnakeEnv)
+//=============================================================================
+    fn move_counts(&self) -> Vec<u32> {
+        self.world.snakes().iter().map(|snake| snake.move_count()).collect()
+    }
+
+    fn grid(&self, id: usize) -> Vec<f32> {
+        let board = self.world.board();
+        let width = board.width() as usize;
+        let height = board.height() as usize;
+        let plane = width * height;
+        let mut grid = vec![0.0; SnakeEnv::GRID_CHANNELS * plane];
+        let mut set = |channel: usize, pos: Pos| {
+            if pos.x >= 0 && pos.y >= 0 && (pos.x as usize) < width && (pos.y as usize) < height {
+                grid[channel * plane + pos.y as usize * width + pos.x as usize] = 1.0;
+            }
+        };
+
+        for y in 0..board.height() {
+            for x in 0..board.width() {
+                let pos = Pos::new(x, y);
+                if !board.play_area_contains(&pos) {
+                    set(0, pos);
+                }
+            }
+        }
+        for apple in self.world.apples() {
+            set(1, apple.pos);
+        }
+        for (i, snake) in self.world.snakes().iter().enumerate() {
+            let (head, body) = if i == id { (2, 3) } else { (4, 5) };
+            for (k, part) in snake.parts().enumerate() {
+                set(if k == 0 { head } else { body }, part.pos);
+            }
+        }
+        grid
+    }
+
+    //   0..3    cell ahead, to the left and to the right is blocked
+    //   3..7    direction up, down, left, right
+    //   7..11   nearest apple is up, down, left, right of the head
+    //   11..13  head position, 0.0 to 1.0 across the board
+    //   13      length as part of the play area
+    //   14..16  offset to the nearest other head, -1.0 to 1.0
+    fn features(&self, id: usize) -> Vec<f32> {
+        let mut features = vec![0.0; SnakeEnv::FEATURE_COUNT];
+        let snake = self.world.snake(id);
+        if snake.length() == 0 {
+            return features;
+        }
+
+        let board = self.world.board();
+        let head = snake.position();
+        let dir = snake.direction();
+        let flag = |value: bool| if value { 1.0 } else { 0.0 };
+
+        let ahead = [dir, turn_left(dir), turn_right(dir)];
+        for (i, dir) in ahead.iter().enumerate() {
+            features[i] = flag(self.is_blocked(&board.next_position(&head, *dir)));
+        }
+
+        let dirs = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+        for (i, d) in dirs.iter().enumerate() {
+            features[3 + i] = flag(*d == dir);
+        }
+
+        let distance = |pos: &Pos| (pos.x - head.x).abs() + (pos.y - head.y).abs();
+        if let Some(apple) = self.world.apples().iter().min_by_key(|apple| distance(&apple.pos)) {
+            features[7] = flag(apple.pos.y < head.y);
+            features[8] = flag(apple.pos.y > head.y);
+            features[9] = flag(apple.pos.x < head.x);
+            features[10] = flag(apple.pos.x > head.x);
+        }
+
+        let width = board.width() as f32;
+        let height = board.height() as f32;
+        features[11] = head.x as f32 / (width - 1.0);
+        features[12] = head.y as f32 / (height - 1.0);
+        features[13] = snake.length() as f32 / ((width - 2.0) * (height - 2.0));
+
+        let other_head = self.world.snakes().iter().enumerate()
+            .filter(|(i, other)| *i != id && other.is_alive())
+            .map(|(_, other)| other.position())
+            .min_by_key(|pos| distance(pos));
+        if let Some(pos) = other_head {
+            features[14] = (pos.x - head.x) as f32 / width;
+            features[15] = (pos.y - head.y) as f32 / height;
+        }
+        features
+    }
+
+    fn is_blocked(&self, pos: &Pos) -> bool {
+        !self.world.board().play_area_contains(pos)
+            || self.world.snakes().iter().any(|snake| snake.has_position(pos))
+    }
+}
+
+fn turn_left(dir: Direction) -> Direction {
+    match dir {
+        Direction::Up => Direction::Left,
+        Direction::Left => Direction::Down,
+        Direction::Down => Direction::Right,
+        Direction::Right => Direction::Up
+    }
+}
+
+fn turn_right(dir: Direction) -> Direction {
+    opposite_dir(turn_left(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn greedy_actions(env: &SnakeEnv) -> Vec<Option<Direction>> {
+        (0..env.player_count()).map(|id| GreedyAi.next_direction(env.world(), id)).collect()
+    }
+
+    fn assert_shape(env: &SnakeEnv, observation: &Observation, shape: &[usize]) {
+        assert_eq!(observation.shape, shape);
+        assert_eq!(observation.players.len(), env.player_count());
+        let size: usize = shape.iter().product();
+        assert!(observation.players.iter().all(|player| player.len() == size));
+    }
+
+    #[test]
+    fn observations_have_the_shape_of_the_encoding() {
+        let shapes = [
+            (Encoding::Grid, vec![SnakeEnv::GRID_CHANNELS, 20, 23]),
+            (Encoding::Features, vec![SnakeEnv::FEATURE_COUNT])
+        ];
+        for (encoding, shape) in shapes.iter() {
+            let mut env = SnakeEnv::new(23, 20, 2, *encoding);
+            assert_eq!(&env.observation_shape(), shape);
+
+            let observation = env.reset(1);
+            assert_shape(&env, &observation, shape);
+            let actions = greedy_actions(&env);
+            let (observation, rewards, _) = env.step(&actions);
+            assert_shape(&env, &observation, shape);
+            assert_eq!(rewards.len(), 2);
+        }
+    }
+
+    #[test]
+    fn eating_an_apple_is_rewarded() {
+        let mut env = SnakeEnv::new(23, 20, 1, Encoding::Features);
+        env.reset(3);
+        for _ in 0..1000 {
+            let actions = greedy_actions(&env);
+            let (_, rewards, done) = env.step(&actions);
+            assert!(!done);
+            if rewards[0] != 0.0 {
+                assert_eq!(rewards[0], SnakeEnv::APPLE_REWARD);
+                assert_eq!(env.world().apples_eaten(), 1);
+                return;
+            }
+        }
+        panic!("no apple eaten");
+    }
+
+    #[test]
+    fn crashing_is_punished_and_ends_the_episode() {
+        let mut env = SnakeEnv::new(23, 20, 1, Encoding::Grid);
+        env.reset(3);
+        for _ in 0..20 {
+            let (_, rewards, done) = env.step(&[None]);
+            if done {
+                assert_eq!(rewards[0], SnakeEnv::CRASH_REWARD);
+                assert!(!env.world().snake(0).is_alive());
+                return;
+            }
+            assert_eq!(rewards[0], 0.0);
+        }
+        panic!("no crash going straight into the wall");
+    }
+
+    #[test]
+    fn episodes_end_after_max_steps() {
+        let mut env = SnakeEnv::new(23, 20, 1, Encoding::Features);
+        env.set_max_steps(3);
+        env.reset(3);
+        assert!(!env.step(&[None]).2);
+        assert!(!env.step(&[None]).2);
+        assert!(env.step(&[None]).2);
+        assert_eq!(env.world().state(), LevelState::Playing);
+
+        // And reset starts counting again
+        env.reset(3);
+        assert!(!env.step(&[None]).2);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_episode() {
+        let play = |seed: u64| {
+            let mut env = SnakeEnv::new(23, 20, 2, Encoding::Grid);
+            let mut observations = vec![env.reset(seed).players];
+            let mut rewards = Vec::new();
+            for _ in 0..200 {
+                let actions = greedy_actions(&env);
+                let (observation, reward, done) = env.step(&actions);
+                observations.push(observation.players);
+                rewards.push(reward);
+                if done {
+                    break;
+                }
+            }
+            (observations, rewards)
+        };
+        assert!(play(5) == play(5));
+        assert!(play(5) != play(6));
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::VecDeque;
+use crate::sim::*;
+
+//=============================================================================
+//    ArenaStyle
+//=============================================================================
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ArenaStyle {
+    Obstacles,  // Blocks scattered all over
+    Rooms,      // Walls across the arena with doors in them
+    Symmetric   // Blocks mirrored into every quarter, the same for each gate
+}
+
+impl ArenaStyle {
+    pub const ALL: [ArenaStyle; 3] = [ArenaStyle::Obstacles, ArenaStyle::Rooms, ArenaStyle::Symmetric];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArenaStyle::Obstacles => "Obstacles",
+            ArenaStyle::Rooms => "Rooms",
+            ArenaStyle::Symmetric => "Symmetric"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ArenaStyle> {
+        ArenaStyle::ALL.iter().copied().find(|style| style.name().eq_ignore_ascii_case(name))
+    }
+}
+
+//=============================================================================
+//    Arena generator
+//=============================================================================
+// Random boards from a seed, with the size and the gates of the built-in
+// arena. The cells in front of the gates stay free for the snakes to come
+// in, and every floor cell can be reached from every gate: floor cut off by
+// the walls is walled in, and a layout that parts the gates or ends up
+// without any walls inside is thrown away. When none of GENERATE_ATTEMPTS
+// layouts works out the plain arena is used, so there is always a board.
+const GENERATE_ATTEMPTS: u32 = 20;
+const GATE_CLEARANCE: i32 = 5;  // Free cells in front of a gate
+const OBSTACLE_AREA: i32 = 25;  // Cells per obstacle
+const MIN_OBSTACLES: i32 = 2;
+const MAX_OBSTACLE_SIZE: u32 = 3;
+const ROOM_SIZE: i32 = 8;
+const DOOR_SIZE: i32 = 2;
+
+impl Board {
+    // The style comes from the seed too
+    pub fn random_arena(width: i32, height: i32, seed: u64) -> Board {
+        let style = ArenaStyle::ALL[Rng::new(seed).rand() as usize % ArenaStyle::ALL.len()];
+        Board::generate(width, height, style, seed)
+    }
+
+    // Named "<style> <seed>", see Board::from_arena_name
+    pub fn generate(width: i32, height: i32, style: ArenaStyle, seed: u64) -> Board {
+        let mut rng = Rng::new(seed);
+        let mut board = Board::new(width, height);
+
+        for _ in 0..GENERATE_ATTEMPTS {
+            let mut attempt = Board::new(width, height);
+            match style {
+                ArenaStyle::Obstacles => {
+                    let count = obstacle_count(width, height);
+                    attempt.add_obstacles(&mut rng, count, width, height)
+                },
+                ArenaStyle::Rooms => attempt.add_rooms(&mut rng),
+                ArenaStyle::Symmetric => attempt.add_symmetric_obstacles(&mut rng)
+            }
+            attempt.clear_gate_entries();
+            if attempt.connect_gates() && attempt.has_inner_walls() {
+                board = attempt;
+                break;
+            }
+        }
+
+        board.set_name(format!("{} {}", style.name(), seed));
+        board
+    }
+
+    // The board a generated arena's name stands for, so replays can make it again
+    pub fn from_arena_name(name: &str, width: i32, height: i32) -> Option<Board> {
+        let (style, seed) = name.split_once(' ')?;
+        let style = ArenaStyle::from_name(style)?;
+        Some(Board::generate(width, height, style, seed.parse().ok()?))
+    }
+
+//=============================================================================
+//  Private methods (Arena generator)
+//=============================================================================
+    // Blocks up to MAX_OBSTACLE_SIZE cells wide and high, inside the top
+    // left width x height cells of the board
+    fn add_obstacles(&mut self, rng: &mut Rng, count: i32, width: i32, height: i32) {
+        for _ in 0..count {
+            let size_x = (rng.rand() % MAX_OBSTACLE_SIZE + 1) as i32;
+            let size_y = (rng.rand() % MAX_OBSTACLE_SIZE + 1) as i32;
+            let x = (rng.rand() % (width - 2) as u32) as i32 + 1;
+            let y = (rng.rand() % (height - 2) as u32) as i32 + 1;
+            for dy in 0..size_y {
+                for dx in 0..size_x {
+                    if x + dx < width && y + dy < height {
+                        self.set_wall(&Pos::new(x + dx, y + dy), true);
+                    }
+                }
+            }
+        }
+    }
+
+    // Obstacles in the top left quarter, mirrored into the other three
+    fn add_symmetric_obstacles(&mut self, rng: &mut Rng) {
+        let (width, height) = (self.width(), self.height());
+        let count = (obstacle_count(width, height) + 3) / 4;
+        self.add_obstacles(rng, count, (width + 1) / 2, (height + 1) / 2);
+
+        for y in 1..(height + 1) / 2 {
+            for x in 1..(width + 1) / 2 {
+                if self.is_wall(&Pos::new(x, y)) {
+                    self.set_wall(&Pos::new(width - 1 - x, y), true);
+                    self.set_wall(&Pos::new(x, height - 1 - y), true);
+                    self.set_wall(&Pos::new(width - 1 - x, height - 1 - y), true);
+                }
+            }
+        }
+    }
+
+    // Walls across the board about ROOM_SIZE apart, with a door into every
+    // room along each wall
+    fn add_rooms(&mut self, rng: &mut Rng) {
+        let (width, height) = (self.width(), self.height());
+        let columns = room_walls(rng, width);
+        let rows = room_walls(rng, height);
+
+        for &x in &columns {
+            for y in 1..height - 1 {
+                self.set_wall(&Pos::new(x, y), true);
+            }
+        }
+        for &y in &rows {
+            for x in 1..width - 1 {
+                self.set_wall(&Pos::new(x, y), true);
+            }
+        }
+
+        let row_bounds = bounds(&rows, height);
+        let column_bounds = bounds(&columns, width);
+        for &x in &columns {
+            for &(top, bottom) in &row_bounds {
+                let door = random_door(rng, top, bottom);
+                for y in door..door + DOOR_SIZE {
+                    self.set_wall(&Pos::new(x, y), false);
+                }
+            }
+        }
+        for &y in &rows {
+            for &(left, right) in &column_bounds {
+                let door = random_door(rng, left, right);
+                for x in door..door + DOOR_SIZE {
+                    self.set_wall(&Pos::new(x, y), false);
+                }
+            }
+        }
+    }
+
+    // The snakes come in straight, and need room to the sides to turn
+    fn clear_gate_entries(&mut self) {
+        for id in 0..self.max_players() {
+            let gate = self.gate(id);
+            let mut pos = gate.pos;
+            for _ in 0..GATE_CLEARANCE {
+                pos = pos.offset(gate.dir);
+                for side in -1..=1 {
+                    let cell = match gate.dir {
+                        Direction::Up | Direction::Down => Pos::new(pos.x + side, pos.y),
+                        Direction::Left | Direction::Right => Pos::new(pos.x, pos.y + side)
+                    };
+                    let inside = cell.x > 0 && cell.y > 0 
+                        && cell.x < self.width() - 1 && cell.y < self.height() - 1;
+                    if inside {
+                        self.set_wall(&cell, false);
+                    }
+                }
+            }
+        }
+    }
+
+    // Walls in the floor the first gate can't reach. False if another gate
+    // can't reach it either.
+    fn connect_gates(&mut self) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let entries: Vec<Pos> = (0..self.max_players())
+            .map(|id| {
+                let gate = self.gate(id);
+                gate.pos.offset(gate.dir)
+            })
+            .collect();
+
+        let mut reached = vec![false; (width * height) as usize];
+        let mut queue = VecDeque::new();
+        reached[(entries[0].y * width + entries[0].x) as usize] = true;
+        queue.push_back(entries[0]);
+
+        let dirs = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+        while let Some(pos) = queue.pop_front() {
+            for dir in dirs.iter() {
+                let next = pos.offset(*dir);
+                if !self.is_wall(&next) && !reached[(next.y * width + next.x) as usize] {
+                    reached[(next.y * width + next.x) as usize] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if entries.iter().any(|entry| !reached[(entry.y * width + entry.x) as usize]) {
+            return false;
+        }
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                if !reached[(y * width + x) as usize] {
+                    self.set_wall(&Pos::new(x, y), true);
+                }
+            }
+        }
+        true
+    }
+
+    fn has_inner_walls(&self) -> bool {
+        (1..self.height() - 1).any(|y| (1..self.width() - 1).any(|x| self.is_wall(&Pos::new(x, y))))
+    }
+}
+
+// For the whole board, at least MIN_OBSTACLES however small it is
+fn obstacle_count(width: i32, height: i32) -> i32 {
+    ((width - 2) * (height - 2) / OBSTACLE_AREA).max(MIN_OBSTACLES)
+}
+
+// Positions of the walls across a side of the given length, evenly spread
+// give or take a cell, and clear of the outer wall
+fn room_walls(rng: &mut Rng, length: i32) -> Vec<i32> {
+    let count = (length - 2) / ROOM_SIZE;
+    (1..=count)
+        .map(|i| i * (length - 1) / (count + 1) + (rng.rand() % 3) as i32 - 1)
+        .filter(|&pos| pos > 2 && pos < length - 3)
+        .collect()
+}
+
+// The floor between the walls, as (first, last) cells
+fn bounds(walls: &[i32], length: i32) -> Vec<(i32, i32)> {
+    let mut bounds = Vec::new();
+    let mut start = 1;
+    for &wall in walls {
+        bounds.push((start, wall - 1));
+        start = wall + 1;
+    }
+    bounds.push((start, length - 2));
+    bounds
+}
+
+fn random_door(rng: &mut Rng, first: i32, last: i32) -> i32 {
+    let room = (last - first + 2 - DOOR_SIZE).max(1) as u32;
+    first + (rng.rand() % room) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [(i32, i32); 5] = [(12, 10), (17, 15), (23, 20), (31, 24), (60, 45)];
+    const SEEDS: u64 = 100;
+
+    // The floor cells reachable from the cell in front of the first gate
+    fn flood_fill(board: &Board) -> Vec<bool> {
+        let width = board.width();
+        let gate = board.gate(0);
+        let start = gate.pos.offset(gate.dir);
+        let mut reached = vec![false; (width * board.height()) as usize];
+        let mut queue = VecDeque::new();
+        reached[(start.y * width + start.x) as usize] = true;
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            for dir in [Direction::Up, Direction::Down, Direction::Left, Direction::Right].iter() {
+                let next = pos.offset(*dir);
+                if !board.is_wall(&next) && !reached[(next.y * width + next.x) as usize] {
+                    reached[(next.y * width + next.x) as usize] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+
+    #[test]
+    fn every_floor_cell_can_be_reached() {
+        for &(width, height) in SIZES.iter() {
+            for &style in ArenaStyle::ALL.iter() {
+                for seed in 0..SEEDS {
+                    let board = Board::generate(width, height, style, seed);
+                    let reached = flood_fill(&board);
+                    for y in 1..height - 1 {
+                        for x in 1..width - 1 {
+                            let pos = Pos::new(x, y);
+                            assert!(board.is_wall(&pos) || reached[(y * width + x) as usize],
+                                    "{} at {}x{}: {:?} cut off", board.name(), width, height, pos);
+                        }
+                    }
+                    for id in 0..board.max_players() {
+                        let gate = board.gate(id);
+                        assert!(!board.is_wall(&gate.pos.offset(gate.dir)),
+                                "{} at {}x{}: gate {} blocked", board.name(), width, height, id + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_arena_has_obstacles() {
+        for &(width, height) in SIZES.iter() {
+            for &style in ArenaStyle::ALL.iter() {
+                for seed in 0..SEEDS {
+                    let board = Board::generate(width, height, style, seed);
+                    assert!(board.has_inner_walls(), "{} at {}x{} is empty", board.name(), width, height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_arenas_are_mirrored() {
+        for &(width, height) in SIZES.iter() {
+            let board = Board::generate(width, height, ArenaStyle::Symmetric, 7);
+            for y in 0..height {
+                for x in 0..width {
+                    let wall = board.is_wall(&Pos::new(x, y));
+                    assert_eq!(wall, board.is_wall(&Pos::new(width - 1 - x, y)));
+                    assert_eq!(wall, board.is_wall(&Pos::new(x, height - 1 - y)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arena_names_make_the_same_board() {
+        for &style in ArenaStyle::ALL.iter() {
+            let board = Board::generate(23, 20, style, 42);
+            let again = Board::from_arena_name(board.name(), 23, 20).unwrap();
+            assert_eq!(again.to_map_text(), board.to_map_text());
+        }
+        assert!(Board::from_arena_name("Caves 42", 23, 20).is_none());
+        assert!(Board::from_arena_name("Rooms many", 23, 20).is_none());
+    }
+}
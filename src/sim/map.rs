@@ -0,0 +1,250 @@
+use std::error::Error;
+use std::fmt;
+use crate::sim::*;
+
+//=============================================================================
+//    Map files
+//=============================================================================
+// A board as text, one character per cell:
+//   #    wall
+//   .    floor
+//   +    floor apples grow on; without any, apples grow anywhere
+//   1-4  the gate player N comes in through, in the outer wall
+// The lines above the grid hold settings, for now only `name = Cross`, and
+// lines starting with ';' are comments:
+//   ; Four pillars
+//   name = Pillars
+//   ###########
+//   #.........#
+//   #..#...#..#
+//   #....+....#
+//   #..#...#..#
+//   #.........#
+//   ####1#2####
+#[derive(Debug)]
+pub struct MapError {
+    pub line: usize,  // 0 when not about a single line
+    pub message: String
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}: {}", self.line, self.message)
+        } else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
+
+impl Error for MapError { }
+
+pub const TILE_WALL: char = '#';
+pub const TILE_FLOOR: char = '.';
+pub const TILE_APPLE_ZONE: char = '+';
+const COMMENT: char = ';';
+pub(crate) const MIN_SIZE: i32 = 5;
+pub(crate) const MAX_SIZE: i32 = 100;
+
+impl Board {
+    pub fn parse_map(name: &str, text: &str) -> Result<Board, MapError> {
+        let mut map_name = name.to_string();
+        let mut rows: Vec<(usize, Vec<char>)> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with(COMMENT) {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                if !rows.is_empty() {
+                    return Err(map_error(line_number, "settings must come before the grid"));
+                }
+                match key.trim() {
+                    "name" => map_name = value.trim().to_string(),
+                    key => return Err(map_error(line_number, &format!("unknown setting '{}'", key)))
+                }
+            } else {
+                rows.push((line_number, line.chars().collect()));
+            }
+        }
+
+        let height = rows.len() as i32;
+        let width = rows.first().map_or(0, |(_, row)| row.len() as i32);
+        if !(MIN_SIZE..=MAX_SIZE).contains(&width) || !(MIN_SIZE..=MAX_SIZE).contains(&height) {
+            return Err(map_error(0, &format!("the grid must be {} to {} cells on each side",
+                                              MIN_SIZE, MAX_SIZE)));
+        }
+
+        let mut board = Board::empty(map_name, width, height);
+        let mut gates: Vec<Option<Gate>> = vec![None; Board::MAX_GATES];
+        let mut floor_count = 0;
+
+        for (y, (line_number, row)) in rows.iter().enumerate() {
+            if row.len() as i32 != width {
+                return Err(map_error(*line_number, "all grid lines must be equally long"));
+            }
+
+            for (x, tile) in row.iter().enumerate() {
+                let pos = Pos::new(x as i32, y as i32);
+                match *tile {
+                    TILE_WALL => board.set_wall(&pos, true),
+                    TILE_FLOOR => {
+                        board.set_wall(&pos, false);
+                        floor_count += 1;
+                    },
+                    TILE_APPLE_ZONE => {
+                        board.add_apple_zone(pos);
+                        floor_count += 1;
+                    },
+                    '1'..='4' => {
+                        let id = tile.to_digit(10).unwrap() as usize - 1;
+                        let dir = gate_direction(&pos, width, height)
+                            .ok_or_else(|| map_error(*line_number,
+                                "gates must be in the outer wall, away from the corners"))?;
+                        if gates[id].is_some() {
+                            return Err(map_error(*line_number, &format!("gate {} appears twice", id + 1)));
+                        }
+                        gates[id] = Some(Gate { pos, dir });
+                    },
+                    tile => return Err(map_error(*line_number, &format!("unknown tile '{}'", tile)))
+                }
+            }
+        }
+
+        // Gates are numbered from 1 without gaps, one per player
+        let gate_count = gates.iter().take_while(|gate| gate.is_some()).count();
+        if gate_count == 0 || gates[gate_count..].iter().any(|gate| gate.is_some()) {
+            return Err(map_error(0, "gates must be numbered 1, 2, ... without gaps"));
+        }
+        if floor_count == 0 {
+            return Err(map_error(0, "the map has no floor"));
+        }
+        for gate in gates.into_iter().flatten() {
+            board.add_gate(gate);
+        }
+
+        Ok(board)
+    }
+
+    pub fn to_map_text(&self) -> String {
+        let mut text = String::new();
+        if !self.name().is_empty() {
+            text.push_str(&format!("name = {}\n", self.name()));
+        }
+
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let pos = Pos::new(x, y);
+                let gate = (0..self.max_players()).find(|id| self.gate(*id).pos == pos);
+                // The outer wall is kept even if the board wraps
+                let outer_wall = x == 0 || y == 0 || x == self.width() - 1 || y == self.height() - 1;
+                let tile = if let Some(id) = gate {
+                    std::char::from_digit(id as u32 + 1, 10).unwrap()
+                } else if self.is_wall(&pos) || outer_wall {
+                    TILE_WALL
+                } else if self.apple_zone().contains(&pos) {
+                    TILE_APPLE_ZONE
+                } else {
+                    TILE_FLOOR
+                };
+                text.push(tile);
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+}
+
+// Snakes come in away from the wall the gate is in
+fn gate_direction(pos: &Pos, width: i32, height: i32) -> Option<Direction> {
+    let on_side = pos.x == 0 || pos.x == width - 1;
+    let on_end = pos.y == 0 || pos.y == height - 1;
+    if on_side == on_end {  // A corner or inside
+        None
+    } else if pos.y == 0 {
+        Some(Direction::Down)
+    } else if pos.y == height - 1 {
+        Some(Direction::Up)
+    } else if pos.x == 0 {
+        Some(Direction::Right)
+    } else {
+        Some(Direction::Left)
+    }
+}
+
+fn map_error(line: usize, message: &str) -> MapError {
+    MapError { line, message: message.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+; A gate in every wall
+name = Test
+#1######
+#......#
+#.#..+.#
+2......#
+#......4
+###3####
+";
+
+    fn error(text: &str) -> String {
+        Board::parse_map("Bad", text).err().expect("map should not parse").to_string()
+    }
+
+    #[test]
+    fn parses_tiles_and_gates() {
+        let board = Board::parse_map("File name", MAP).unwrap();
+        assert_eq!(board.name(), "Test");
+        assert_eq!((board.width(), board.height()), (8, 6));
+
+        assert!(board.is_wall(&Pos::new(0, 0)));
+        assert!(board.is_wall(&Pos::new(2, 2)));
+        assert!(!board.is_wall(&Pos::new(1, 1)));
+        assert!(!board.is_wall(&Pos::new(5, 2)));
+        assert_eq!(board.apple_zone(), &[Pos::new(5, 2)]);
+
+        assert_eq!(board.max_players(), 4);
+        let gates: Vec<Gate> = (0..4).map(|id| board.gate(id)).collect();
+        assert_eq!(gates[0], Gate { pos: Pos::new(1, 0), dir: Direction::Down });
+        assert_eq!(gates[1], Gate { pos: Pos::new(0, 3), dir: Direction::Right });
+        assert_eq!(gates[2], Gate { pos: Pos::new(3, 5), dir: Direction::Up });
+        assert_eq!(gates[3], Gate { pos: Pos::new(7, 4), dir: Direction::Left });
+        assert!(gates.iter().all(|gate| board.is_wall(&gate.pos)));
+    }
+
+    #[test]
+    fn map_text_parses_back_to_the_same_board() {
+        let board = Board::parse_map("Test", MAP).unwrap();
+        let again = Board::parse_map("", &board.to_map_text()).unwrap();
+        assert_eq!(again.name(), "Test");
+        assert_eq!(again.to_map_text(), board.to_map_text());
+    }
+
+    #[test]
+    fn rejects_bad_grids() {
+        assert_eq!(error("#####\n#...#\n#..#\n#...#\n##1##\n"), 
+                   "line 3: all grid lines must be equally long");
+        assert_eq!(error("#####\n#...#\n#.x.#\n#...#\n##1##\n"), "line 3: unknown tile 'x'");
+        assert_eq!(error("#####\n#...#\n#.1.#\n#...#\n#####\n"), 
+                   "line 3: gates must be in the outer wall, away from the corners");
+        assert_eq!(error("1####\n#...#\n#...#\n#...#\n#####\n"), 
+                   "line 1: gates must be in the outer wall, away from the corners");
+        assert_eq!(error("##1##\n#...#\n#...#\n#...#\n##1##\n"), "line 5: gate 1 appears twice");
+        assert_eq!(error("##1##\n#...#\n#...#\n#...#\n##3##\n"), 
+                   "gates must be numbered 1, 2, ... without gaps");
+        assert_eq!(error("##1##\n#####\n#####\n#####\n#####\n"), "the map has no floor");
+        assert_eq!(error("#1#\n#.#\n###\n"), "the grid must be 5 to 100 cells on each side");
+        assert_eq!(error("size = 5\n##1##\n#...#\n#...#\n#...#\n#####\n"), 
+                   "line 1: unknown setting 'size'");
+        assert_eq!(error("##1##\n#...#\nname = Late\n#...#\n#...#\n#####\n"), 
+                   "line 3: settings must come before the grid");
+    }
+}
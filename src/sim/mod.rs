@@ -0,0 +1,31 @@
+//=============================================================================
+//    sim - the game rules without a window
+//=============================================================================
+// Nothing in here may use macroquad: the world only changes through
+// World::step with explicit inputs and elapsed time, so games can run and be
+// checked headless. Drawing, sound and keyboard input live in the game layer.
+mod board;
+mod map;
+mod generator;
+mod rng;
+mod timer;
+mod apple;
+mod snake;
+mod world;
+mod replay;
+mod controller;
+mod ai;
+mod env;
+
+pub use board::*;
+pub use map::*;
+pub use generator::*;
+pub use rng::*;
+pub use timer::*;
+pub use apple::*;
+pub use snake::*;
+pub use world::*;
+pub use replay::*;
+pub use controller::*;
+pub use ai::*;
+pub use env::*;
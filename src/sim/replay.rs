@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write, BufReader, BufWriter};
+use crate::sim::*;
+
+//=============================================================================
+//    Replay
+//=============================================================================
+// Everything needed to play a game again: the world settings and the
+// direction input of every player for every tick since World::reset.
+//
+// File layout (little endian): "SNKR", u8 version, u64 seed, u16 width,
+// u16 height, u8 player count, u8 map name length and the map name (version
+// 2 on), u16 apple goal and u16 start speed in tenths of a cell per second
+// (version 3 on), u8 flags with 1 for a wrapping board (version 4 on), u32
+// tick count, then runs of identical ticks as u16 run length followed by one
+// input byte per player (0 = none, 1 = up, 2 = down, 3 = left, 4 = right).
+pub struct Replay {
+    pub seed: u64,
+    pub width: i32,
+    pub height: i32,
+    pub player_count: usize,
+    pub map: String,  // Empty for the built-in arena
+    pub apple_goal: u32,
+    pub start_speed: f32,
+    pub wrap: bool,
+    inputs: Vec<Option<Direction>>
+}
+
+impl Replay {
+    const SIGNATURE: &'static [u8] = b"SNKR";
+    const VERSION: u8 = 4;
+    const SPEED_SCALE: f32 = 10.0;
+    const FLAG_WRAP: u8 = 1;
+
+    // Takes the board and the rules from the world, just after its reset
+    pub fn new(seed: u64, world: &World) -> Replay {
+        let board = world.board();
+        Replay { 
+            seed, 
+            width: board.width(), 
+            height: board.height(), 
+            player_count: world.player_count(), 
+            map: board.name().to_string(), 
+            apple_goal: world.apple_goal(),
+            start_speed: world.start_speed(),
+            wrap: board.wraps(),
+            inputs: Vec::new() 
+        }
+    }
+
+    // Inputs of one tick, missing players count as no input
+    pub fn record(&mut self, inputs: &[Option<Direction>]) {
+        for i in 0..self.player_count {
+            self.inputs.push(inputs.get(i).copied().flatten());
+        }
+    }
+
+    pub fn tick_count(&self) -> usize {
+        self.inputs.len() / self.player_count
+    }
+
+    pub fn tick_inputs(&self, tick: usize) -> Option<&[Option<Direction>]> {
+        let start = tick * self.player_count;
+        self.inputs.get(start..start + self.player_count)
+    }
+
+    pub fn save(&self, file_name: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_name)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(Replay::SIGNATURE)?;
+        writer.write_all(&[Replay::VERSION])?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&(self.width as u16).to_le_bytes())?;
+        writer.write_all(&(self.height as u16).to_le_bytes())?;
+        writer.write_all(&[self.player_count as u8])?;
+        if self.map.len() > u8::MAX as usize {
+            return Err(invalid_data("map name too long"));
+        }
+        writer.write_all(&[self.map.len() as u8])?;
+        writer.write_all(self.map.as_bytes())?;
+        writer.write_all(&(self.apple_goal as u16).to_le_bytes())?;
+        writer.write_all(&((self.start_speed * Replay::SPEED_SCALE).round() as u16).to_le_bytes())?;
+        writer.write_all(&[if self.wrap { Replay::FLAG_WRAP } else { 0 }])?;
+        writer.write_all(&(self.tick_count() as u32).to_le_bytes())?;
+
+        let ticks: Vec<&[Option<Direction>]> = self.inputs.chunks(self.player_count).collect();
+        let mut start = 0;
+        while start < ticks.len() {
+            let mut end = start + 1;
+            while end < ticks.len() && ticks[end] == ticks[start] && end - start < u16::MAX as usize {
+                end += 1;
+            }
+
+            writer.write_all(&((end - start) as u16).to_le_bytes())?;
+            for input in ticks[start] {
+                writer.write_all(&[input_code(*input)])?;
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    pub fn load(file_name: &str) -> io::Result<Replay> {
+        let mut reader = BufReader::new(File::open(file_name)?);
+        Replay::read(&mut reader)
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Replay> {
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature)?;
+        if signature != Replay::SIGNATURE {
+            return Err(invalid_data("not a replay file"));
+        }
+        let version = read_bytes::<R, 1>(reader)?[0];
+        if version == 0 || version > Replay::VERSION {
+            return Err(invalid_data("unsupported replay version"));
+        }
+
+        let seed = u64::from_le_bytes(read_bytes(reader)?);
+        let width = u16::from_le_bytes(read_bytes(reader)?) as i32;
+        let height = u16::from_le_bytes(read_bytes(reader)?) as i32;
+        let player_count = read_bytes::<R, 1>(reader)?[0] as usize;
+        let map = if version >= 2 {
+            let mut name = vec![0u8; read_bytes::<R, 1>(reader)?[0] as usize];
+            reader.read_exact(&mut name)?;
+            String::from_utf8(name).map_err(|_| invalid_data("bad map name"))?
+        } else {
+            String::new()
+        };
+        let (apple_goal, start_speed) = if version >= 3 {
+            let apple_goal = u16::from_le_bytes(read_bytes(reader)?) as u32;
+            let speed = u16::from_le_bytes(read_bytes(reader)?) as f32 / Replay::SPEED_SCALE;
+            (apple_goal, speed)
+        } else {
+            (0, Snake::DEFAULT_SPEED)
+        };
+        let wrap = if version >= 4 {
+            read_bytes::<R, 1>(reader)?[0] & Replay::FLAG_WRAP != 0
+        } else {
+            false
+        };
+        let tick_count = u32::from_le_bytes(read_bytes(reader)?) as usize;
+        if player_count == 0 {
+            return Err(invalid_data("replay has no players"));
+        } else if player_count > World::MAX_PLAYERS {
+            return Err(invalid_data("replay has too many players"));
+        }
+        if !(MIN_SIZE..=MAX_SIZE).contains(&width) || !(MIN_SIZE..=MAX_SIZE).contains(&height) {
+            return Err(invalid_data("bad board size"));
+        }
+
+        let mut replay = Replay { 
+            seed, width, height, player_count, map, apple_goal, start_speed, wrap, inputs: Vec::new() 
+        };
+        let mut tick_inputs = vec![None; player_count];
+        while replay.tick_count() < tick_count {
+            let run_length = u16::from_le_bytes(read_bytes(reader)?) as usize;
+            for input in tick_inputs.iter_mut() {
+                *input = code_input(read_bytes::<R, 1>(reader)?[0])?;
+            }
+            if run_length == 0 || replay.tick_count() + run_length > tick_count {
+                return Err(invalid_data("bad input run"));
+            }
+            for _ in 0..run_length {
+                replay.record(&tick_inputs);
+            }
+        }
+
+        Ok(replay)
+    }
+}
+
+fn input_code(input: Option<Direction>) -> u8 {
+    match input {
+        None => 0,
+        Some(Direction::Up) => 1,
+        Some(Direction::Down) => 2,
+        Some(Direction::Left) => 3,
+        Some(Direction::Right) => 4
+    }
+}
+
+fn code_input(code: u8) -> io::Result<Option<Direction>> {
+    match code {
+        0 => Ok(None),
+        1 => Ok(Some(Direction::Up)),
+        2 => Ok(Some(Direction::Down)),
+        3 => Ok(Some(Direction::Left)),
+        4 => Ok(Some(Direction::Right)),
+        _ => Err(invalid_data("bad input code"))
+    }
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_world(seed: u64, wrap: bool) -> World {
+        let board = Board::random_arena(23, 20, seed);
+        let mut world = World::with_board(board, seed);
+        world.set_apple_goal(30);
+        world.set_start_speed(4.5);
+        world.set_wrap(wrap);
+        world
+    }
+
+    fn final_state(world: &World) -> (u64, LevelState, Vec<i32>, Vec<Vec<Pos>>) {
+        let scores = (0..world.player_count()).map(|i| world.score(i)).collect();
+        let bodies = world.snakes().iter()
+            .map(|snake| snake.parts().map(|part| part.pos).collect())
+            .collect();
+        (world.tick_count(), world.state(), scores, bodies)
+    }
+
+    fn replay_round_trip(seed: u64, wrap: bool) {
+        let mut world = new_world(seed, wrap);
+        world.reset(2, seed);
+        let mut replay = Replay::new(seed, &world);
+        let mut controllers = [Difficulty::Normal.new_ai(), Difficulty::Hard.new_ai()];
+        while world.state() != LevelState::GameOver && world.tick_count() < 20_000 {
+            let inputs: Vec<Option<Direction>> = controllers.iter_mut().enumerate()
+                .map(|(i, controller)| controller.next_direction(&world, i))
+                .collect();
+            replay.record(&inputs);
+            world.step(&inputs);
+        }
+
+        let mut file = Vec::new();
+        replay.write(&mut file).unwrap();
+        let loaded = Replay::read(&mut &file[..]).unwrap();
+        assert_eq!(loaded.seed, seed);
+        assert_eq!((loaded.width, loaded.height), (23, 20));
+        assert_eq!(loaded.player_count, 2);
+        assert_eq!(loaded.map, world.board().name());
+        assert_eq!(loaded.apple_goal, 30);
+        assert_eq!(loaded.start_speed, 4.5);
+        assert_eq!(loaded.wrap, wrap);
+        assert_eq!(loaded.tick_count(), world.tick_count() as usize);
+
+        let board = Board::from_arena_name(&loaded.map, loaded.width, loaded.height).unwrap();
+        let mut replayed = World::with_board(board, loaded.seed);
+        replayed.set_apple_goal(loaded.apple_goal);
+        replayed.set_start_speed(loaded.start_speed);
+        replayed.set_wrap(loaded.wrap);
+        replayed.reset(loaded.player_count, loaded.seed);
+        for tick in 0..loaded.tick_count() {
+            replayed.step(loaded.tick_inputs(tick).unwrap());
+        }
+        assert_eq!(final_state(&replayed), final_state(&world));
+    }
+
+    #[test]
+    fn replays_to_the_same_world() {
+        replay_round_trip(3, false);
+    }
+
+    #[test]
+    fn replays_to_the_same_world_on_a_wrapping_board() {
+        replay_round_trip(4, true);
+    }
+
+    #[test]
+    fn rejects_bad_board_sizes() {
+        let world = new_world(5, false);
+        for &(width, height) in [(2, 20), (23, 0), (101, 20), (23, 1000)].iter() {
+            let mut replay = Replay::new(5, &world);
+            replay.width = width;
+            replay.height = height;
+            replay.record(&[None]);
+            let mut file = Vec::new();
+            replay.write(&mut file).unwrap();
+            let error = Replay::read(&mut &file[..]).err().expect("size should be rejected");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Replay::read(&mut &b"PAK2...."[..]).is_err());
+        assert!(Replay::read(&mut &b"SNKR\x09"[..]).is_err());
+        assert!(Replay::read(&mut &b"SNKR\x04\x01"[..]).is_err());
+    }
+}
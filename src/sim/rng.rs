@@ -0,0 +1,29 @@
+//=============================================================================
+//    Rng
+//=============================================================================
+// PCG32 with the constants of macroquad's rand, so a seed gives the same
+// apple sequence as rand::srand(seed) used to, but every world owns its state
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    const MULTIPLIER: u64 = 6364136223846793005;
+    const INCREMENT: u64 = 1442695040888963407;
+
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0 };
+        rng.rand();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.rand();
+        rng
+    }
+
+    pub fn rand(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state.wrapping_mul(Rng::MULTIPLIER).wrapping_add(Rng::INCREMENT);
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+}
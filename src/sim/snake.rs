@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use crate::sim::*;
+
+//=================================================================================================
+//    Snake
+//=================================================================================================
+#[derive(Clone)]
+pub struct SnakePart {
+    pub pos: Pos,
+    pub dir: Direction
+}
+
+impl SnakePart {
+    fn new(pos: Pos, dir: Direction) -> SnakePart {
+        SnakePart { pos, dir }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Crash {
+    Wall, Itself, Opponent
+}
+
+pub struct Snake {
+    initial_pos: Pos,
+    initial_dir: Direction,
+    parts: VecDeque<SnakePart>,
+    removed_part: Option<SnakePart>,
+    move_count: u32,
+    new_dir: Direction,
+    initial_speed: u32,
+    speed: u32,
+    step_progress: u32,
+    dying_timer: TickTimer,
+    alive: bool
+}
+
+impl Snake {
+    // Speeds are in tenths of a cell per second, kept integral so stepping
+    // never depends on float rounding
+    const SPEED_SCALE: u32 = 10;
+    pub const DEFAULT_SPEED: f32 = 3.0;
+    const SPEED_GAIN: u32 = 1;
+    const MAX_SPEED: u32 = 100;
+    const STEP_DISTANCE: u32 = Snake::SPEED_SCALE * TICK_RATE;
+    pub const STUN_TICKS: u32 = TICK_RATE * 4 / 5;
+    const NORMAL_DYING_TICKS: u32 = TICK_RATE / 5;
+    const FAST_DYING_TICKS: u32 = TICK_RATE / 20;
+
+    // The snake starts with its head at initial_pos, its body trailing
+    // behind, heading initial_dir
+    pub fn new(initial_pos: Pos, initial_dir: Direction) -> Snake {
+        let parts: VecDeque<SnakePart> = VecDeque::new(); 
+        let removed_part = None;
+        let move_count = 0;
+        let new_dir = initial_dir;
+        let initial_speed = Snake::speed_steps(Snake::DEFAULT_SPEED);
+        let speed = 0; 
+        let step_progress = 0;
+        let dying_timer = TickTimer::new(0);
+        let alive = true;
+
+        let mut snake = Snake { initial_pos, initial_dir, parts, removed_part, move_count, 
+                                new_dir, initial_speed, speed, step_progress, dying_timer, alive };
+        snake.reset();
+        snake
+    }
+
+    pub fn reset(&mut self) {
+        let dir = self.initial_dir;
+        let mut pos = self.initial_pos;
+
+        self.parts.clear();
+        for _ in 0..3 {
+            self.parts.push_back(SnakePart::new(pos, dir));
+            pos = pos.offset(opposite_dir(dir));
+        }
+        self.removed_part = None;
+        self.move_count = 0;
+        self.new_dir = dir;
+        self.speed = self.initial_speed; 
+        self.step_progress = 0; 
+        self.alive = true;
+    }
+
+    // Cells per second after the next reset, in steps of a tenth
+    pub fn set_initial_speed(&mut self, speed: f32) {
+        self.initial_speed = Snake::speed_steps(speed);
+    }
+
+    pub fn initial_speed(&self) -> f32 {
+        self.initial_speed as f32 / Snake::SPEED_SCALE as f32
+    }
+
+    pub fn set_direction(&mut self, dir: Direction) -> bool {
+        let mut dir_changed = false;
+        let current_dir = self.direction();
+        let invalid_dir = opposite_dir(current_dir); 
+
+        if dir != current_dir && dir != invalid_dir {
+            self.new_dir = dir;
+            dir_changed = true;
+        }
+
+        dir_changed
+    }
+
+    pub fn kill_self(&mut self) {
+        self.alive = false;
+
+        let interval = if self.parts.len() < 10 {
+            Snake::NORMAL_DYING_TICKS
+        } else {
+            Snake::FAST_DYING_TICKS
+        };
+
+        self.dying_timer.set(interval);
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    pub fn length(&self) -> u32 {
+        self.parts.len() as u32
+    }
+
+    // Cells per second
+    pub fn speed(&self) -> f32 {
+        self.speed as f32 / Snake::SPEED_SCALE as f32
+    }
+
+    // Number of steps taken since the last reset
+    pub fn move_count(&self) -> u32 {
+        self.move_count
+    }
+
+    // Head first
+    pub fn parts(&self) -> impl Iterator<Item = &SnakePart> {
+        self.parts.iter()
+    }
+
+    // Where each part was before the last step, head first: the cell of the
+    // part behind it, or for the tail the cell it left (its own if it grew)
+    pub fn previous_positions(&self) -> impl Iterator<Item = Pos> + '_ {
+        let tail = self.removed_part.as_ref().or_else(|| self.parts.back()).map(|part| part.pos);
+        self.parts.iter().skip(1).map(|part| part.pos).chain(tail)
+    }
+
+    // How far the snake has got towards its next step, 0.0 right after one,
+    // given the part of a tick elapsed since the last tick
+    pub fn step_fraction(&self, tick_fraction: f32) -> f32 {
+        let progress = self.step_progress as f32 + tick_fraction * self.speed as f32;
+        (progress / Snake::STEP_DISTANCE as f32).min(1.0)
+    }
+
+    pub fn position(&self) -> Pos {
+        match self.parts.front() {
+            Some(part) => part.pos, 
+            None => Pos::new(-1, -1), 
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.parts.front().unwrap().dir
+    }
+
+    pub fn new_position(&self, board: &Board) -> Pos {
+        board.next_position(&self.position(), self.new_dir)
+    }
+
+    pub fn has_position(&self, pos: &Pos) -> bool {
+        self.parts.iter().any(|part| part.pos == *pos)
+    }
+
+    pub fn eat_apples(&mut self, apples: &mut Vec<Apple>) -> bool {
+        let position = self.position();
+
+        match apples.iter().position(|apple| apple.pos == position) {
+            Some(i) => {  // Run into an apple
+                apples.remove(i);
+                self.restore_removed_part();  // Snake glows!
+                self.speed = (self.speed + Snake::SPEED_GAIN).min(Snake::MAX_SPEED);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn check_collision(&self, board: &Board, opponents: &[&Snake]) -> Option<Crash> {
+        let pos = self.new_position(board);
+
+        if !board.play_area_contains(&pos) {  // Collision with walls
+            Some(Crash::Wall)
+        } else if self.has_position(&pos) {  // Collision with tail
+            Some(Crash::Itself)
+        } else if opponents.iter().any(|opponent| opponent.has_position(&pos)) {
+            Some(Crash::Opponent)
+        } else {
+            None
+        }
+    }
+
+    // Call once per tick, returns true when the snake is due to take a step.
+    // The leftover progress is kept, so the average speed is exact.
+    pub fn check_update_time(&mut self) -> bool {
+        self.step_progress += self.speed;
+        if self.step_progress >= Snake::STEP_DISTANCE {
+            self.step_progress -= Snake::STEP_DISTANCE;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn update(&mut self, board: &Board) {
+        if self.alive { 
+            self.move_count += 1;
+            let new_head = SnakePart::new(self.new_position(board), self.new_dir);
+            self.parts.push_front(new_head); 
+            self.removed_part = self.parts.pop_back();
+        }
+    }
+
+    pub fn dying(&mut self) -> bool {  // Return false when finished dying
+        if self.dying_timer.update() && !self.parts.is_empty() {
+            self.parts.pop_back();
+            self.dying_timer.reset();
+        }
+        
+        !self.parts.is_empty()
+    }
+
+//=================================================================================================    
+//  Private methods (Snake)
+//=================================================================================================    
+    fn speed_steps(speed: f32) -> u32 {
+        ((speed * Snake::SPEED_SCALE as f32).round() as u32).clamp(1, Snake::MAX_SPEED)
+    }
+
+    fn restore_removed_part(&mut self) {
+        if let Some(part) = self.removed_part.take() {
+            self.parts.push_back(part);
+        }
+    }
+}
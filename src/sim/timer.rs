@@ -0,0 +1,40 @@
+//=============================================================================
+//    Ticks
+//=============================================================================
+// The world advances in fixed ticks, never by measured frame time, so the
+// same seed and inputs give the same game on any machine
+pub const TICK_RATE: u32 = 60;
+pub const TICK_TIME: f32 = 1.0 / TICK_RATE as f32;
+
+//=============================================================================
+//    TickTimer
+//=============================================================================
+pub struct TickTimer {
+    duration: u32, 
+    counter: u32
+}
+
+impl TickTimer {
+    pub fn new(duration: u32) -> TickTimer {
+        let counter = 0;
+        TickTimer { duration, counter }
+    }
+
+    pub fn set(&mut self, duration: u32) {
+        self.duration = duration;
+        self.counter = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.counter = 0;
+    }
+
+    // Call once per tick
+    pub fn update(&mut self) -> bool {
+        if self.counter < self.duration {
+            self.counter += 1;
+        }
+
+        self.counter >= self.duration
+    }
+}
@@ -0,0 +1,604 @@
+use crate::sim::*;
+
+//=============================================================================
+//    World
+//=============================================================================
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum LevelState {
+    GetReady, SnakeEntering, Playing, Exiting, LevelComplete, Stunned, Dying, GameOver
+}
+
+// What happened during a step, for the game layer to play sounds and log
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Event {
+    Turned(usize),
+    AteApple(usize),
+    Crashed(usize, Crash),
+    GatesClosed,
+    GatesOpened,
+    LevelComplete,
+    GameOver
+}
+
+pub struct World {
+    board: Board,
+    player_count: usize,
+    snakes: Vec<Snake>,
+    apples: Vec<Apple>,
+    scores: Vec<i32>,
+    apple_goal: u32,
+    apples_eaten: u32,
+    rng: Rng,
+    state: LevelState,
+    state_ticks: u32,
+    game_ticks: u32,
+    tick_count: u64,
+    spawn_timer: TickTimer,
+    delay_timer: TickTimer
+}
+
+impl World {
+    pub const MAX_PLAYERS: usize = Board::MAX_GATES;
+    pub const GET_READY_TICKS: u32 = TICK_RATE;
+    const SPAWN_TICKS: u32 = 2 * TICK_RATE;
+    const MAX_APPLES: usize = 3;
+
+    pub fn new(width: i32, height: i32, seed: u64) -> World {
+        World::with_board(Board::new(width, height), seed)
+    }
+
+    pub fn with_board(board: Board, seed: u64) -> World {
+        // Each snake waits just outside its gate
+        let snakes = (0..World::MAX_PLAYERS)
+            .map(|i| {
+                let gate = board.gate(i);
+                Snake::new(gate.pos.offset(opposite_dir(gate.dir)), gate.dir)
+            })
+            .collect();
+
+        World {
+            board,
+            player_count: 1,
+            snakes,
+            apples: Vec::new(),
+            scores: vec![0; World::MAX_PLAYERS],
+            apple_goal: 0,
+            apples_eaten: 0,
+            rng: Rng::new(seed),
+            state: LevelState::GetReady,
+            state_ticks: 0,
+            game_ticks: 0,
+            tick_count: 0,
+            spawn_timer: TickTimer::new(World::SPAWN_TICKS),
+            delay_timer: TickTimer::new(Snake::STUN_TICKS)
+        }
+    }
+
+    // Every game starts from the seed alone, so a replay only has to store
+    // the seed and the inputs
+    pub fn reset(&mut self, player_count: usize, seed: u64) {
+        self.player_count = player_count.clamp(1, World::MAX_PLAYERS.min(self.board.max_players()));
+        self.rng = Rng::new(seed);
+        self.board.set_gate_count(self.player_count);
+        self.board.open_gates();
+
+        for snake in &mut self.snakes {
+            snake.reset();
+        }
+
+        self.apples.clear();
+        for score in &mut self.scores {
+            *score = 0;
+        }
+        self.apples_eaten = 0;
+        self.game_ticks = 0;
+        self.tick_count = 0;
+        self.spawn_timer.reset();
+        self.delay_timer.reset();
+        self.set_state(LevelState::GetReady);
+    }
+
+    // Advances the world by one tick (TICK_TIME seconds). inputs[i] is the
+    // direction requested by player i, if any; directions only change while
+    // playing. Snakes always move in player order within a tick.
+    pub fn step(&mut self, inputs: &[Option<Direction>]) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.tick_count += 1;
+        self.state_ticks += 1;
+
+        if self.state == LevelState::Playing {
+            for (i, input) in inputs.iter().enumerate().take(self.player_count) {
+                if let Some(dir) = input {
+                    if self.snakes[i].set_direction(*dir) {
+                        events.push(Event::Turned(i));
+                    }
+                }
+            }
+        }
+
+        match self.state {
+            LevelState::GetReady => {
+                if self.state_ticks >= World::GET_READY_TICKS {
+                    self.set_state(LevelState::SnakeEntering);
+                }
+            },
+            LevelState::SnakeEntering => {
+                self.update_actors(&mut events);
+                // All snakes move alike until the first is all the way in
+                let player = &self.snakes[0];
+                if player.move_count() > player.length() {
+                    self.set_state(LevelState::Playing);
+                    self.board.close_gates();
+                    events.push(Event::GatesClosed);
+                }
+            },
+            LevelState::Playing => {
+                self.game_ticks += 1;
+                self.update_actors(&mut events);
+                if self.state == LevelState::Playing && self.goal_reached() 
+                        && !self.board.gates_opened() {
+                    self.board.open_gates();
+                    events.push(Event::GatesOpened);
+                }
+            },
+            LevelState::Exiting => {
+                self.update_actors(&mut events);
+                // Done once a snake is all the way out
+                let board = &self.board;
+                let exited = self.snakes().iter()
+                    .any(|snake| snake.parts().all(|part| board.is_wall(&part.pos)));
+                if exited {
+                    self.set_state(LevelState::LevelComplete);
+                    events.push(Event::LevelComplete);
+                }
+            },
+            LevelState::LevelComplete => { },
+            LevelState::Stunned => {
+                if self.delay_timer.update() {
+                    self.set_state(LevelState::Dying);
+                    self.delay_timer.reset();
+                }
+            },
+            LevelState::Dying => {
+                let mut finished = false;
+                for snake in &mut self.snakes {
+                    if !snake.is_alive() && !snake.dying() {
+                        finished = true;
+                    }
+                }
+                if finished {
+                    self.set_state(LevelState::GameOver);
+                    events.push(Event::GameOver);
+                }
+            },
+            LevelState::GameOver => { }
+        }
+
+        events
+    }
+
+    // With a goal, the gates open again once the players have eaten that many
+    // apples, no more apples grow and the level is complete when a snake has
+    // left through a gate. 0 plays on until the game is over. Kept on reset.
+    pub fn set_apple_goal(&mut self, apple_goal: u32) {
+        self.apple_goal = apple_goal;
+    }
+
+    pub fn apple_goal(&self) -> u32 {
+        self.apple_goal
+    }
+
+    pub fn apples_eaten(&self) -> u32 {
+        self.apples_eaten
+    }
+
+    // Cells per second the snakes start at from the next reset on
+    pub fn set_start_speed(&mut self, speed: f32) {
+        for snake in &mut self.snakes {
+            snake.set_initial_speed(speed);
+        }
+    }
+
+    pub fn start_speed(&self) -> f32 {
+        self.snakes[0].initial_speed()
+    }
+
+    // Snakes leaving the board come back in at the other side. Kept on reset.
+    // The board's outer row and column turn into floor, see Board::set_wrap.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.board.set_wrap(wrap);
+    }
+
+    // Apples only where a test puts them
+    #[cfg(test)]
+    pub(crate) fn set_apples(&mut self, positions: &[Pos]) {
+        self.apples = positions.iter().map(|pos| Apple { pos: *pos, age: 0 }).collect();
+        self.spawn_timer.set(u32::MAX);
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn state(&self) -> LevelState {
+        self.state
+    }
+
+    // Ticks spent in the current state
+    pub fn state_ticks(&self) -> u32 {
+        self.state_ticks
+    }
+
+    // Ticks spent playing this game
+    pub fn game_ticks(&self) -> u32 {
+        self.game_ticks
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.player_count
+    }
+
+    // Only the snakes taking part in the current game
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes[0..self.player_count]
+    }
+
+    pub fn snake(&self, id: usize) -> &Snake {
+        &self.snakes[id]
+    }
+
+    pub fn apples(&self) -> &[Apple] {
+        &self.apples
+    }
+
+    pub fn score(&self, id: usize) -> i32 {
+        self.scores[id]
+    }
+
+//=============================================================================
+//  Private methods (World)
+//=============================================================================
+    fn set_state(&mut self, state: LevelState) {
+        self.state = state;
+        self.state_ticks = 0;
+    }
+
+    fn goal_reached(&self) -> bool {
+        self.apple_goal > 0 && self.apples_eaten >= self.apple_goal
+    }
+
+    fn opponents(&self, id: usize) -> Vec<&Snake> {
+        self.snakes().iter().enumerate()
+            .filter(|(i, _)| *i != id)
+            .map(|(_, snake)| snake)
+            .collect()
+    }
+
+    fn update_scores(&mut self) {
+        for i in 0..self.player_count {
+            self.scores[i] += self.snakes[i].length() as i32 / 5;
+        }
+    }
+
+    fn update_actors(&mut self, events: &mut Vec<Event>) {
+        if self.spawn_timer.update() && self.apples.len() < World::MAX_APPLES 
+                && !self.goal_reached() {
+            let apple = Apple::random_spawn(&self.board, &self.snakes[0..self.player_count], 
+                                            &mut self.rng);
+            self.apples.push(apple);
+            self.spawn_timer.reset();
+        }
+
+        for apple in &mut self.apples {
+            apple.update();
+        }
+
+        // Every snake due to move is checked against the board and the others as 
+        // they are before anyone moves, so a crash earlier in the same tick doesn't 
+        // let the rest move unchecked. A snake already in a gate is on its way out.
+        let due: Vec<bool> = self.snakes[0..self.player_count].iter_mut()
+            .map(|snake| snake.check_update_time())
+            .collect();
+        let checked = matches!(self.state, LevelState::Playing | LevelState::Exiting);
+        let crashes: Vec<Option<Crash>> = (0..self.player_count)
+            .map(|i| {
+                let snake = &self.snakes[i];
+                if !due[i] || !checked || self.board.is_wall(&snake.position()) {
+                    return None;
+                }
+                snake.check_collision(&self.board, &self.opponents(i)).or_else(|| {
+                    // Two heads moving into the same cell
+                    let pos = snake.new_position(&self.board);
+                    let head_on = (0..self.player_count).any(|j| {
+                        j != i && due[j] && self.snakes[j].new_position(&self.board) == pos
+                    });
+                    if head_on { Some(Crash::Opponent) } else { None }
+                })
+            })
+            .collect();
+
+        for i in 0..self.player_count {
+            if !due[i] {
+                continue;
+            }
+
+            match crashes[i] {
+                Some(crash) => {
+                    self.snakes[i].update(&self.board);
+                    self.snakes[i].kill_self();
+                    self.set_state(LevelState::Stunned);
+                    events.push(Event::Crashed(i, crash));
+                },
+                None => {
+                    self.snakes[i].update(&self.board);
+                    if self.snakes[i].eat_apples(&mut self.apples) {
+                        self.apples_eaten += 1;
+                        events.push(Event::AteApple(i));
+                    }
+                    // Only an open gate lets a snake into the wall
+                    if self.state == LevelState::Playing 
+                            && self.board.is_wall(&self.snakes[i].position()) {
+                        self.set_state(LevelState::Exiting);
+                    }
+                }
+            }
+            self.update_scores();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Gates 1 and 2 in the bottom wall, two cells apart
+    const MAP: &str = "\
+#########
+#.......#
+#.......#
+#.......#
+#.......#
+#.......#
+#.......#
+#.......#
+###1#2###
+";
+
+    // Apples only grow where a test puts them
+    fn new_world(player_count: usize) -> World {
+        let board = Board::parse_map("Test", MAP).unwrap();
+        let mut world = World::with_board(board, 1);
+        world.reset(player_count, 1);
+        world.spawn_timer.set(u32::MAX);
+        world
+    }
+
+    fn start(world: &mut World) {
+        while world.state() != LevelState::Playing {
+            world.step(&[]);
+        }
+    }
+
+    // Steps until snake 0 has moved once, returns the events on the way
+    fn step_move(world: &mut World, inputs: &[Option<Direction>]) -> Vec<Event> {
+        let move_count = world.snake(0).move_count();
+        let mut events = Vec::new();
+        while world.snake(0).move_count() == move_count && world.state() == LevelState::Playing {
+            events.extend(world.step(inputs));
+        }
+        events
+    }
+
+    fn add_apple(world: &mut World, x: i32, y: i32) {
+        world.apples.push(Apple { pos: Pos::new(x, y), age: 0 });
+    }
+
+    #[test]
+    fn snakes_enter_through_their_gates() {
+        let mut world = new_world(2);
+        start(&mut world);
+        assert_eq!(world.snake(0).position(), Pos::new(3, 5));
+        assert_eq!(world.snake(1).position(), Pos::new(5, 5));
+        assert!(!world.board().gates_opened());
+    }
+
+    #[test]
+    fn snake_crashes_into_the_wall() {
+        let mut world = new_world(1);
+        start(&mut world);
+
+        let mut events = Vec::new();
+        while world.state() == LevelState::Playing {
+            events.extend(world.step(&[None]));
+        }
+        assert!(events.contains(&Event::Crashed(0, Crash::Wall)));
+        assert_eq!(world.state(), LevelState::Stunned);
+        assert!(!world.snake(0).is_alive());
+
+        while world.state() != LevelState::GameOver {
+            world.step(&[None]);
+        }
+    }
+
+    #[test]
+    fn snake_crashes_into_itself() {
+        let mut world = new_world(1);
+        start(&mut world);
+        add_apple(&mut world, 3, 4);
+        add_apple(&mut world, 3, 3);
+        step_move(&mut world, &[None]);
+        step_move(&mut world, &[None]);
+        assert_eq!(world.snake(0).length(), 5);
+
+        step_move(&mut world, &[Some(Direction::Right)]);
+        step_move(&mut world, &[Some(Direction::Down)]);
+        let events = step_move(&mut world, &[Some(Direction::Left)]);
+        assert!(events.contains(&Event::Crashed(0, Crash::Itself)));
+        assert_eq!(world.state(), LevelState::Stunned);
+    }
+
+    #[test]
+    fn snake_crashes_into_the_other_snake() {
+        let mut world = new_world(2);
+        start(&mut world);
+        step_move(&mut world, &[Some(Direction::Right), None]);
+        let events = step_move(&mut world, &[Some(Direction::Right), None]);
+        assert!(events.contains(&Event::Crashed(0, Crash::Opponent)));
+        assert!(!world.snake(0).is_alive());
+        assert!(world.snake(1).is_alive());
+    }
+
+    #[test]
+    fn snakes_crashing_in_the_same_tick_both_crash() {
+        let mut world = new_world(2);
+        start(&mut world);
+
+        let mut events = Vec::new();
+        while world.state() == LevelState::Playing {
+            events.extend(world.step(&[None, None]));
+        }
+        assert!(events.contains(&Event::Crashed(0, Crash::Wall)));
+        assert!(events.contains(&Event::Crashed(1, Crash::Wall)));
+        assert!(!world.snake(1).is_alive());
+    }
+
+    #[test]
+    fn snakes_meeting_head_on_both_crash() {
+        let mut world = new_world(2);
+        start(&mut world);
+        let events = step_move(&mut world, &[Some(Direction::Right), Some(Direction::Left)]);
+        assert!(events.contains(&Event::Crashed(0, Crash::Opponent)));
+        assert!(events.contains(&Event::Crashed(1, Crash::Opponent)));
+    }
+
+    #[test]
+    fn apples_grow_the_snake_and_long_snakes_score() {
+        let mut world = new_world(1);
+        start(&mut world);
+        let speed = world.snake(0).speed();
+
+        add_apple(&mut world, 3, 4);
+        let events = step_move(&mut world, &[None]);
+        assert!(events.contains(&Event::AteApple(0)));
+        assert_eq!(world.snake(0).length(), 4);
+        assert_eq!(world.apples_eaten(), 1);
+        assert!(world.apples().is_empty());
+        assert!(world.snake(0).speed() > speed);
+        assert_eq!(world.score(0), 0);
+
+        // Every step scores a point per five parts
+        add_apple(&mut world, 3, 3);
+        step_move(&mut world, &[None]);
+        assert_eq!(world.snake(0).length(), 5);
+        assert_eq!(world.score(0), 1);
+        step_move(&mut world, &[None]);
+        assert_eq!(world.snake(0).length(), 5);
+        assert_eq!(world.score(0), 2);
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_the_same_game() {
+        let play = || {
+            let mut world = World::new(23, 20, 7);
+            world.reset(2, 7);
+            let mut controllers = [Difficulty::Normal.new_ai(), Difficulty::Hard.new_ai()];
+            while world.state() != LevelState::GameOver && world.tick_count() < 10_000 {
+                let inputs: Vec<Option<Direction>> = controllers.iter_mut().enumerate()
+                    .map(|(i, controller)| controller.next_direction(&world, i))
+                    .collect();
+                world.step(&inputs);
+            }
+            (world.tick_count(), world.score(0), world.score(1), world.snake(0).length())
+        };
+        assert_eq!(play(), play());
+    }
+    fn wrapping_world(player_count: usize) -> World {
+        let mut world = new_world(player_count);
+        world.set_wrap(true);
+        start(&mut world);
+        world
+    }
+
+    // Moves snake 0 once per input and checks where its head ends up
+    fn assert_moves(world: &mut World, moves: &[(Option<Direction>, i32, i32)]) {
+        for &(input, x, y) in moves {
+            let events = step_move(world, &[input]);
+            assert!(!events.iter().any(|event| matches!(event, Event::Crashed(..))), 
+                    "crashed on the way to ({}, {})", x, y);
+            assert_eq!(world.snake(0).position(), Pos::new(x, y));
+        }
+    }
+
+    #[test]
+    fn snakes_cross_every_edge_of_a_wrapping_board() {
+        let mut world = wrapping_world(1);
+        assert_eq!(world.snake(0).position(), Pos::new(3, 5));
+        assert!(!world.board().is_wall(&Pos::new(0, 4)));
+        assert!(!world.board().is_wall(&Pos::new(3, 8)));
+
+        assert_moves(&mut world, &[
+            (None, 3, 4), (None, 3, 3), (None, 3, 2), (None, 3, 1), (None, 3, 0),
+            (None, 3, 8),                            // Top to bottom
+            (Some(Direction::Left), 2, 8), (None, 1, 8), (None, 0, 8),
+            (None, 8, 8),                            // Left to right
+            (Some(Direction::Down), 8, 0),           // Bottom to top
+            (Some(Direction::Right), 0, 0)           // Right to left
+        ]);
+        assert!(world.snake(0).is_alive());
+        assert_eq!(world.state(), LevelState::Playing);
+    }
+
+    #[test]
+    fn snakes_crash_into_bodies_across_the_edge() {
+        let mut world = wrapping_world(2);
+        let right = Some(Direction::Right);
+        let up = Some(Direction::Up);
+        let down = Some(Direction::Down);
+        let left = Some(Direction::Left);
+
+        // Snake 1 lies along the bottom row when snake 0 comes over the top
+        let moves = [(right, right), (up, down), (up, down), (up, down), 
+                     (up, left), (up, left)];
+        for (input_0, input_1) in moves.iter() {
+            let events = step_move(&mut world, &[*input_0, *input_1]);
+            assert!(!events.iter().any(|event| matches!(event, Event::Crashed(..))));
+        }
+        assert_eq!(world.snake(0).position(), Pos::new(4, 0));
+        assert_eq!(world.snake(1).position(), Pos::new(4, 8));
+
+        let events = step_move(&mut world, &[up, left]);
+        assert!(events.contains(&Event::Crashed(0, Crash::Opponent)));
+        assert!(world.snake(1).is_alive());
+    }
+
+    #[test]
+    fn apples_across_the_edge_are_next_to_the_head() {
+        let mut world = wrapping_world(1);
+        add_apple(&mut world, 3, 8);
+        for _ in 0..5 {
+            step_move(&mut world, &[None]);
+        }
+        assert_eq!(world.snake(0).position(), Pos::new(3, 0));
+        let events = step_move(&mut world, &[None]);
+        assert!(events.contains(&Event::AteApple(0)));
+        assert_eq!(world.snake(0).length(), 4);
+    }
+
+    #[test]
+    fn computer_players_take_the_way_across_the_edge() {
+        let mut world = wrapping_world(1);
+        assert_moves(&mut world, &[
+            (Some(Direction::Left), 2, 5), (None, 1, 5), (Some(Direction::Up), 1, 4)
+        ]);
+
+        // Two cells to the left across the edge, six to the right
+        add_apple(&mut world, 8, 4);
+        assert_eq!(GreedyAi.next_direction(&world, 0), Some(Direction::Left));
+        assert_eq!(PathAi.next_direction(&world, 0), Some(Direction::Left));
+        assert_eq!(AggressiveAi.next_direction(&world, 0), Some(Direction::Left));
+    }
+}
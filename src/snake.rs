@@ -1,13 +1,12 @@
-use std::collections::LinkedList;
 use macroquad::prelude::*;
-use crate::gamestate::*;
 use crate::gamescene::*;
-use crate::apple::*;
 use crate::common::*;
+use crate::sim::{Snake, Direction};
 
 //=================================================================================================
-//    Snake
+//    SnakeSprite
 //=================================================================================================
+// Draws a sim::Snake, either as blocks or from the 16x16 frames of a snake atlas
 struct SnakeFrame;
 
 impl SnakeFrame {
@@ -23,214 +22,27 @@ impl SnakeFrame {
     const DEAD_HEAD: f32 = 9.0;
 }
 
-#[derive(Clone)]
-struct SnakePart {
-    pos: Vec2,
-    dir: Direction
-}
-
-impl SnakePart {
-    fn new(pos: Vec2, dir: Direction) -> SnakePart {
-        SnakePart { pos, dir }
-    }
-}
-
-pub struct Snake {
-    id: u8,
+// Players beyond the two atlases share one, tinted
+pub struct SnakeSprite {
     pub color: Color,
-    initial_pos: Vec2,
-    parts: LinkedList<SnakePart>,
-    removed_part: Option<SnakePart>,
-    tongue_anim_flag: bool,
-    new_dir: Direction,
-    speed: f32,
-    timer: Timer,
-    alive: bool,
-    bound: Rect
+    pub texture: usize,
+    pub tint: Color
 }
 
-impl Snake {
-    const INITIAL_SPEED: f32 = 3.0;
-    const MAX_SPEED: f32 = 10.0;
-    pub const STUN_INTERVAL: f32 = 0.8;
-    const NORMAL_DYING_INTERVAL: f32 = 0.2;
-    const FAST_DYING_INTERVAL: f32 = 0.05;
-
-    pub fn new(id: u8, color: Color, initial_pos: Vec2, bound: Rect) -> Snake {
-        let parts: LinkedList<SnakePart> = LinkedList::new(); 
-        let removed_part = None;
-        let tongue_anim_flag = false;
-        let new_dir = Direction::Up;
-        let speed = 0.0; 
-        let timer = Timer::new(0.0);
-        let alive = true;
-
-        let mut snake = Snake { id, color, initial_pos, parts, removed_part, tongue_anim_flag, 
-                                new_dir, speed, timer, alive, bound };
-        snake.reset();
-        snake
-    }
-
-    pub fn reset(&mut self) {
-        let x = self.initial_pos.x;
-        let y = self.initial_pos.y;
-
-        self.parts.clear();
-        self.parts.push_back(SnakePart::new(Vec2::new(x, y), Direction::Up) );
-        self.parts.push_back(SnakePart::new(Vec2::new(x, y + 1.0), Direction::Up) );
-        self.parts.push_back(SnakePart::new(Vec2::new(x, y + 2.0), Direction::Up) );
-        self.new_dir = Direction::Up;
-        self.speed = Snake::INITIAL_SPEED; 
-        self.timer = Timer::new(1.0 / self.speed); 
-        self.alive = true;
-    }
-
-    pub fn id(&self) -> u8 {
-        self.id
-    }
-
-    pub fn set_direction(&mut self, dir: Direction) -> bool {
-        let mut dir_changed = false;
-        let current_dir = self.direction();
-        let invalid_dir = opposite_dir(current_dir); 
-
-        if dir != current_dir && dir != invalid_dir {
-            self.new_dir = dir;
-            dir_changed = true;
-        }
-
-        dir_changed
-    }
-
-    pub fn kill_self(&mut self) {
-        self.alive = false;
-        let length: f32  = self.parts.len() as f32;
-
-        let interval: f32;
-        if length < 10.0 {
-            interval = Snake::NORMAL_DYING_INTERVAL;
-        } else {
-            interval = Snake::FAST_DYING_INTERVAL;
-        }
-
-        self.timer.set(interval);
-    }
-
-    pub fn is_alive(&self) -> bool {
-        self.alive
-    }
-
-    pub fn length(&self) -> u32 {
-        self.parts.len() as u32
-    }
-
-    pub fn _speed(&self) -> f32 {
-        self.speed
-    }
-
-    pub fn position(&self) -> Vec2 {
-        match self.parts.front() {
-            Some(part) => part.pos, 
-            None => Vec2::new(-1.0, -1.0), 
-        }
-    }
-
-    pub fn new_position(&self) -> Vec2 {
-        let cur_pos = self.position();
-        let offset = dir_to_vec2(self.new_dir);
-        Vec2::new(cur_pos.x + offset.x, cur_pos.y + offset.y)
-    }
-
-    pub fn has_position(&self, pos: &Vec2) -> bool {
-        for part in &self.parts {
-            if *pos == part.pos {
-                return true;
-            }
-        }
-        return false;
+impl SnakeSprite {
+    pub fn new(color: Color, texture: usize, tint: Color) -> SnakeSprite {
+        SnakeSprite { color, texture, tint }
     }
 
-    pub fn eat_apples(&mut self, apples: &mut Vec<Apple>) -> bool {
-        let mut result = false;
-        let mut i = 0;
-        
-        for apple in apples.iter() {
-            if self.position() == apple.pos {  // Run into an apple
-                result = true;
-                apples.remove(i);
-                self.restore_removed_path();  // Snake glows!
-                self.speed += 0.1;
-                if self.speed > Snake::MAX_SPEED {
-                    self.speed = Snake::MAX_SPEED;
-                }
-                self.timer.set(1.0 / self.speed);
-                println!("Speed: {}", self.speed);
-                break;
-            }
-
-            i += 1;
-        }
-
-        result
-    }
-
-    pub fn check_collision(&self, gamestate: &GameState, opponent_id: i32) -> bool {
-        let mut collided = false;
-        let pos = self.new_position();
-
-        if !self.bound.contains(pos) {  // Collision with walls
-            println!("Player {} crashed into the wall!", self.id + 1);
-            collided = true;
-        } else if self.is_collided_with_snake(&pos, &self) {  // Collision with tail
-            println!("Player {} crashed into yourself!", self.id + 1);
-            collided = true;
-        } else {
-            let opponent_optional = gamestate.player_by_id(opponent_id);
-            match opponent_optional {  // Collision with the opponent
-                Some(opponent) => if self.is_collided_with_snake(&pos, opponent) {  
-                    println!("Player {} crashed into the opponent!", self.id + 1);
-                    collided = true;
-                },
-                None => { }
-            }
-        }
-        
-        collided
-    }
-
-    pub fn check_update_time(&mut self) -> bool {
-        self.timer.update()
-    }
-
-    pub fn update(&mut self) {
-        if self.alive { 
-            if self.alive {  // If it's still alive after collision check
-                self.tongue_anim_flag = !self.tongue_anim_flag;
-                let new_head = SnakePart::new(self.new_position(), self.new_dir);
-                self.parts.push_front(new_head); 
-                let removed_part = self.parts.pop_back().unwrap();
-                self.removed_part = Some(removed_part);
-            }
-            self.timer.reset();  
-        }
-    }
-
-    pub fn dying(&mut self) -> bool {  // Return false when finished dying
-        if self.timer.update() && self.parts.len() != 0 {
-            self.parts.pop_back().unwrap();
-            self.timer.reset();
-        }
-        
-        self.parts.len() != 0
-    }
-
-    pub fn draw_basic(&self, scene: &GameScene) {
+    // step is how far the snake is into its next step, parts are drawn that far
+    // from their previous cell to their current one
+    pub fn draw_basic(&self, snake: &Snake, step: f32, scene: &GameScene) {
         let mut is_head = true;
         let mut color = self.color;
 
-        for part in &self.parts {
-            if part.pos.y <= self.bound.bottom() {
-                scene.draw_block(&part.pos, &color);
+        for pos in self.part_positions(snake, step) {
+            if self.is_visible(&pos, scene) {
+                scene.draw_block(&pos, &color);
             }
 
             if is_head { 
@@ -242,8 +54,9 @@ impl Snake {
         }
     }
 
-    pub fn draw(&self, texture: &Texture2D, draw_tongue: bool, scene: &GameScene) {
-        let length: u32 = self.parts.len() as u32;
+    pub fn draw(&self, snake: &Snake, step: f32, texture: &Texture2D, draw_tongue: bool, 
+                scene: &GameScene) {
+        let length: u32 = snake.length();
         if length == 0 {
             return
         }
@@ -251,18 +64,18 @@ impl Snake {
         let mut frame_index: f32;
         let mut rotation: f32;
         let mut prev_dir: Option<Direction> = None;
-        let mut part_index: u32 = 0;
         let tail: u32 = length - 1;
+        let positions = self.part_positions(snake, step);
 
-        for part in &self.parts {
-            match &mut prev_dir {
-                Some(_dir) => { }, 
-                None => prev_dir = Some(part.dir)
+        for (part_index, (part, &pos)) in snake.parts().zip(&positions).enumerate() {
+            let part_index = part_index as u32;
+            if prev_dir.is_none() {
+                prev_dir = Some(part.dir);
             }
 
             if part.dir == prev_dir.unwrap() || part_index == tail {
                 if part_index == 0 {
-                    if self.alive {
+                    if snake.is_alive() {
                         frame_index = SnakeFrame::HEAD;
                     } else {
                         frame_index = SnakeFrame::DEAD_HEAD;
@@ -281,41 +94,54 @@ impl Snake {
                 prev_dir = Some(part.dir);
             }
 
-            if part.pos.y <= self.bound.bottom() {
-                scene.draw_texture_atlas(texture, 16.0, frame_index, &part.pos, 
-                                         &WHITE, rotation);
+            if self.is_visible(&pos, scene) {
+                scene.draw_texture_atlas(texture, 16.0, frame_index, &pos, 
+                                         &self.tint, rotation);
             }
-
-            part_index += 1;
         }
 
-        // Draw tongue
+        // Draw tongue, flicking on every step
         if draw_tongue {
-            frame_index = match self.tongue_anim_flag {
-                false => SnakeFrame::TONGUE_1,
-                true => SnakeFrame::TONGUE_2,
+            frame_index = match snake.move_count() % 2 {
+                0 => SnakeFrame::TONGUE_1,
+                _ => SnakeFrame::TONGUE_2,
             };
-            let cur_dir = self.direction(); 
-            let tongue_pos = self.position() + dir_to_vec2(cur_dir); 
+            let cur_dir = snake.direction(); 
+            let head = snake.position();
+            let offset = pos_to_vec2(head.offset(cur_dir)) - pos_to_vec2(head);
+            let tongue_pos = self.wrap(positions[0] + offset, scene); 
             let tongue_rotation = self.rotation_from_direction(&cur_dir);
-            scene.draw_texture_atlas(texture, 16.0, frame_index, &tongue_pos, &WHITE, tongue_rotation);
+            scene.draw_texture_atlas(texture, 16.0, frame_index, &tongue_pos, &self.tint, tongue_rotation);
         }
     }
 
 //=================================================================================================    
-//  Private methods (Snake)
+//  Private methods (SnakeSprite)
 //=================================================================================================    
-    fn is_collided_with_snake(&self, pos: &Vec2, snake: &Snake) -> bool {
-        let mut collided = false;
-
-        for part in &snake.parts {
-            if *pos == part.pos {
-                collided = true;
-                break
-            }
+    // A part that jumped across the edge of an open board is drawn where it is now
+    fn part_positions(&self, snake: &Snake, step: f32) -> Vec<Vec2> {
+        snake.parts().zip(snake.previous_positions())
+            .map(|(part, previous)| {
+                let pos = pos_to_vec2(part.pos);
+                let distance = (part.pos.x - previous.x).abs() + (part.pos.y - previous.y).abs();
+                if distance > 1 { pos } else { pos_to_vec2(previous).lerp(pos, step) }
+            })
+            .collect()
+    }
+
+    // Parts still outside the gates while entering are hidden
+    fn is_visible(&self, pos: &Vec2, scene: &GameScene) -> bool {
+        pos.x >= 0.0 && pos.x <= scene.width() - 1.0
+            && pos.y >= 0.0 && pos.y <= scene.height() - 1.0
+    }
+
+    // Across the edge of an open board, the tongue shows on the other side
+    fn wrap(&self, pos: Vec2, scene: &GameScene) -> Vec2 {
+        if scene.open_borders() {
+            Vec2::new(pos.x.rem_euclid(scene.width()), pos.y.rem_euclid(scene.height()))
+        } else {
+            pos
         }
-
-        collided
     }
 
     fn rotation_from_direction(&self, dir: &Direction) -> f32 {
@@ -348,22 +174,4 @@ impl Snake {
             }
         }
     }
-
-    fn direction(&self) -> Direction {
-        self.parts.front().unwrap().dir
-    }
-
-    fn restore_removed_path(&mut self) {
-        match &mut self.removed_part {
-            Some(part) => {
-                let restored_part = part.clone();
-                self.parts.push_back(restored_part);
-                self.removed_part = None;
-            },
-            None => { }
-        }
-    }
-
 }
-
-